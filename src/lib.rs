@@ -1,6 +1,12 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use regex::Regex;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -9,21 +15,613 @@ pub enum TronError {
     Io(#[from] std::io::Error),
     #[error("Parse error: {0}")]
     Parse(String),
+    #[error("Parse error at line {line}, column {column}: {message}\n{}", caret_excerpt(.line_text, *.column))]
+    ParseAt {
+        message: String,
+        /// One-based line of the offending text
+        line: usize,
+        /// One-based column of the offending text, counted in characters
+        column: usize,
+        /// The full line of content containing the offending text
+        line_text: String,
+    },
     #[error("Missing placeholder: {0}")]
     MissingPlaceholder(String),
+    #[error("Environment variable {0} is not set")]
+    MissingEnvVar(String),
+    #[error("Placeholder {0} does not occur anywhere it would be substituted")]
+    UnusedPlaceholder(String),
     #[error("Invalid template syntax: {0}")]
     InvalidSyntax(String),
     #[error("Execution error: {0}")]
     ExecutionError(String),
+    #[error("Template {index}: {source}")]
+    AtIndex {
+        index: usize,
+        source: Box<TronError>,
+    },
+    #[error("Execution error: {}", process_failure(.command, *.status, .stderr))]
+    ProcessFailed {
+        /// The command line that was run
+        command: String,
+        /// Exit code, or `None` if the process was terminated by a signal
+        status: Option<i32>,
+        stderr: String,
+    },
+    /// Templates that reference each other in a loop, starting and ending with the same name
+    #[error("Cyclic composition: {}", .0.join(" -> "))]
+    CyclicComposition(Vec<String>),
+    #[error("Composition depth {depth} exceeds the limit of {limit}")]
+    DepthExceeded { depth: usize, limit: usize },
+    #[error("Invalid value for placeholder {placeholder}: {message}")]
+    InvalidValue { placeholder: String, message: String },
+    #[error("Execution error: {message}\n--- generated script ---\n{}", script_excerpt(.script))]
+    ScriptFailed {
+        message: String,
+        /// The complete script handed to rust-script, manifest header included
+        script: String,
+    },
+}
+
+impl TronError {
+    /// The underlying IO error, if this is an `Io` error
+    pub fn io_error(&self) -> Option<&std::io::Error> {
+        match self {
+            TronError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Whether this error reports a placeholder without a value
+    pub fn is_missing_placeholder(&self) -> bool {
+        self.missing_placeholder_name().is_some()
+    }
+
+    /// The name of the placeholder that had no value, if that is what went wrong
+    ///
+    /// Looks through `AtIndex`, so assembler errors report the inner placeholder.
+    pub fn missing_placeholder_name(&self) -> Option<&str> {
+        match self {
+            TronError::MissingPlaceholder(name) => Some(name),
+            TronError::AtIndex { source, .. } => source.missing_placeholder_name(),
+            _ => None,
+        }
+    }
+
+    /// Whether this error came from running a script with rust-script
+    pub fn is_execution_error(&self) -> bool {
+        match self {
+            TronError::ExecutionError(_)
+            | TronError::ProcessFailed { .. }
+            | TronError::ScriptFailed { .. } => true,
+            TronError::AtIndex { source, .. } => source.is_execution_error(),
+            _ => false,
+        }
+    }
+
+    /// Compiler diagnostics found in the output of a failed execution
+    ///
+    /// Returns an empty list for errors that did not come from running a script.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            TronError::ExecutionError(output)
+            | TronError::ProcessFailed { stderr: output, .. }
+            | TronError::ScriptFailed { message: output, .. } => Diagnostic::parse_all(output),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Severity of a compiler diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticLevel {
+    Error,
+    Warning,
+}
+
+/// A compiler message parsed from rustc's human-readable output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub level: DiagnosticLevel,
+    /// Error code such as `E0425`, when rustc reports one
+    pub code: Option<String>,
+    pub message: String,
+    /// Location from the `-->` line following the message, if present
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl Diagnostic {
+    /// Extract every `error[...]:` and `warning:` message from compiler output
+    ///
+    /// Summary lines such as `aborting due to 2 previous errors` are skipped.
+    pub fn parse_all(output: &str) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
+        for line in output.lines() {
+            if let Some(location) = line.trim_start().strip_prefix("--> ") {
+                if let Some(last) = diagnostics.last_mut().filter(|d| d.file.is_none()) {
+                    let mut parts = location.trim().rsplitn(3, ':');
+                    last.column = parts.next().and_then(|part| part.parse().ok());
+                    last.line = parts.next().and_then(|part| part.parse().ok());
+                    last.file = parts.next().map(str::to_string);
+                }
+                continue;
+            }
+
+            let (level, rest) = if let Some(rest) = line.strip_prefix("error") {
+                (DiagnosticLevel::Error, rest)
+            } else if let Some(rest) = line.strip_prefix("warning") {
+                (DiagnosticLevel::Warning, rest)
+            } else {
+                continue;
+            };
+
+            let (code, rest) = match rest.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+                Some((code, rest)) => (Some(code.to_string()), rest),
+                None => (None, rest),
+            };
+            let Some(message) = rest.strip_prefix(": ") else {
+                continue;
+            };
+            if message.starts_with("aborting due to")
+                || message.starts_with("could not compile")
+                || message.ends_with("emitted")
+            {
+                continue;
+            }
+
+            diagnostics.push(Diagnostic {
+                level,
+                code,
+                message: message.to_string(),
+                file: None,
+                line: None,
+                column: None,
+            });
+        }
+
+        diagnostics
+    }
 }
 
 pub type Result<T> = std::result::Result<T, TronError>;
 
+/// Build a template from `content`, set the named values and render it in one step
+///
+/// `generate!("fn @[name]@() {}", name = "main")` evaluates to a
+/// `Result<String>`. Values can be anything implementing `Display`. When
+/// `content` is a string literal, naming a placeholder it does not have is a
+/// compile error:
+///
+/// ```compile_fail
+/// let code = tron::generate!("fn @[name]@() {}", nmae = "main");
+/// ```
+///
+/// Other content is checked when the template is built, failing with
+/// `TronError::MissingPlaceholder` instead. Leaving a placeholder unset fails
+/// the render as usual.
+#[macro_export]
+macro_rules! generate {
+    ($content:literal $(, $name:ident = $value:expr)* $(,)?) => {{
+        $(const _: () = ::std::assert!(
+            $crate::__has_placeholder($content, ::std::stringify!($name)),
+            ::std::concat!(
+                "generate!: the template has no placeholder named `",
+                ::std::stringify!($name),
+                "`"
+            )
+        );)*
+        (|| -> $crate::Result<::std::string::String> {
+            #[allow(unused_mut)]
+            let mut template = $crate::TronTemplate::new($content)?;
+            $(template.set(::std::stringify!($name), &::std::string::ToString::to_string(&$value))?;)*
+            template.render()
+        })()
+    }};
+    ($content:expr $(, $name:ident = $value:expr)* $(,)?) => {
+        (|| -> $crate::Result<::std::string::String> {
+            #[allow(unused_mut)]
+            let mut template = $crate::TronTemplate::new($content)?;
+            $(template.set(::std::stringify!($name), &::std::string::ToString::to_string(&$value))?;)*
+            template.render()
+        })()
+    };
+}
+
+/// Whether `content` has an `@[name]@` tag, for `generate!` to check its names at compile time
+///
+/// Only rules out names that appear in no tag at all; escapes and other
+/// details are left to `TronTemplate::new` and `set` at run time.
+#[doc(hidden)]
+pub const fn __has_placeholder(content: &str, name: &str) -> bool {
+    let content = content.as_bytes();
+    let name = name.as_bytes();
+    let mut start = 0;
+    while start + 1 < content.len() {
+        if content[start] != b'@' || content[start + 1] != b'[' {
+            start += 1;
+            continue;
+        }
+        let mut end = start + 2;
+        while end + 1 < content.len()
+            && !(content[end] == b']' && content[end + 1] == b'@')
+            && !(content[end] == b'@' && content[end + 1] == b'[')
+        {
+            end += 1;
+        }
+        if end + 1 >= content.len() || content[end] == b'@' {
+            start += 1;
+            continue;
+        }
+
+        // Strip trim markers and whitespace, then any `= default`
+        let mut first = start + 2;
+        let mut last = end;
+        while first < last && matches!(content[first], b' ' | b'\t' | b'\r' | b'\n' | b'-') {
+            first += 1;
+        }
+        let mut split = first;
+        while split < last && content[split] != b'=' {
+            split += 1;
+        }
+        last = split;
+        while last > first && matches!(content[last - 1], b' ' | b'\t' | b'\r' | b'\n' | b'-') {
+            last -= 1;
+        }
+
+        if last - first == name.len() {
+            let mut index = 0;
+            while index < name.len() && content[first + index] == name[index] {
+                index += 1;
+            }
+            if index == name.len() {
+                return true;
+            }
+        }
+        start = end + 2;
+    }
+    false
+}
+
+/// A line of content followed by a caret under the given one-based column
+fn caret_excerpt(line_text: &str, column: usize) -> String {
+    format!("{}\n{}^", line_text, " ".repeat(column.saturating_sub(1)))
+}
+
+fn process_failure(command: &str, status: Option<i32>, stderr: &str) -> String {
+    match status {
+        Some(code) => format!("`{}` exited with status {}\n{}", command, code, stderr),
+        None => format!("`{}` was terminated by a signal\n{}", command, stderr),
+    }
+}
+
+/// Longest script, in bytes, shown in full in a `ScriptFailed` message
+const SCRIPT_EXCERPT_LIMIT: usize = 4096;
+
+fn script_excerpt(script: &str) -> Cow<'_, str> {
+    if script.len() <= SCRIPT_EXCERPT_LIMIT {
+        return Cow::Borrowed(script);
+    }
+    let mut end = SCRIPT_EXCERPT_LIMIT;
+    while !script.is_char_boundary(end) {
+        end -= 1;
+    }
+    Cow::Owned(format!(
+        "{}\n... ({} more bytes truncated)",
+        &script[..end],
+        script.len() - end
+    ))
+}
+
+/// Placeholder names reserved for values resolved automatically at render time
+///
+/// - `__file__`: the full path the template was loaded from
+/// - `__template__`: the file name of the template
+/// - `__timestamp__`: seconds since the Unix epoch when rendering
+/// - `__version__`: the version of the tron crate doing the rendering
+///
+/// These are never registered as regular placeholders and cannot be `set`.
+/// `__file__` and `__template__` fail to render on templates without a path.
+pub const MAGIC_PLACEHOLDERS: &[&str] = &["__file__", "__template__", "__timestamp__", "__version__"];
+
+/// Name of a section or block and the byte range of its content
+type SectionSpan<'a> = (&'a str, Range<usize>);
+
+/// Kinds of named regions delimited by an opening and a closing tag
+#[derive(Clone, Copy)]
+enum Region {
+    /// `@[section:name]@ ... @[endsection]@`
+    Section,
+    /// `@[block name]@ ... @[end]@`, overridable through `@[extends path]@`
+    Block,
+}
+
+impl Region {
+    fn start<'a>(self, tag: &Tag<'a>) -> Option<&'a str> {
+        match self {
+            Region::Section => tag.section_start(),
+            Region::Block => tag.block_start(),
+        }
+    }
+
+    fn is_end(self, tag: &Tag<'_>) -> bool {
+        match self {
+            Region::Section => tag.is_section_end(),
+            Region::Block => tag.is_block_end(),
+        }
+    }
+}
+
+/// Every closed region of one kind with the byte range of its content,
+/// ordered by start, plus the offset and description of the first unclosed one
+fn region_spans<'a>(
+    content: &'a str,
+    delimiters: &Delimiters,
+    region: Region,
+) -> (Vec<SectionSpan<'a>>, Option<(usize, &'static str)>) {
+    let mut spans = Vec::new();
+    let mut open: Vec<(&str, usize, usize)> = Vec::new();
+
+    for tag in scan_tags(content, delimiters) {
+        if let Some(name) = region.start(&tag) {
+            open.push((name, tag.span.start, tag.span.end));
+        } else if region.is_end(&tag) {
+            if let Some((name, _, content_start)) = open.pop() {
+                spans.push((name, content_start..tag.span.start));
+            }
+        }
+    }
+    let problem = open.first().map(|&(_, start, _)| {
+        let problem = match region {
+            Region::Section => "section is never closed",
+            Region::Block => "block is never closed",
+        };
+        (start, problem)
+    });
+
+    spans.sort_by_key(|(_, span)| span.start);
+    (spans, problem)
+}
+
+/// Replace the content of every block in `base` that `child` also defines
+///
+/// Blocks the child does not define keep the base's default content. Content
+/// of the child outside its blocks is discarded, as is any block the base
+/// does not declare. A `@[block name]@` without its `@[end]@` in either file
+/// is an error here rather than an ordinary placeholder.
+fn apply_block_overrides(base: &str, child: &str, delimiters: &Delimiters) -> Result<String> {
+    if [base, child]
+        .into_iter()
+        .any(|content| unclosed_block(content, delimiters).is_some())
+    {
+        return Err(TronError::InvalidSyntax(
+            "Cannot apply inheritance: block is never closed".into(),
+        ));
+    }
+    let (child_blocks, _) = region_spans(child, delimiters, Region::Block);
+    let mut overrides: HashMap<&str, &str> = HashMap::new();
+    for (name, span) in child_blocks {
+        overrides.entry(name).or_insert(&child[span]);
+    }
+
+    let mut merged = String::with_capacity(base.len());
+    let mut cursor = 0;
+    // Nesting depth inside an overridden block whose default is being skipped
+    let mut skipping: Option<usize> = None;
+    for tag in scan_tags(base, delimiters) {
+        match skipping {
+            Some(depth) if tag.block_start().is_some() => skipping = Some(depth + 1),
+            Some(0) if tag.is_block_end() => {
+                skipping = None;
+                cursor = tag.span.start;
+            }
+            Some(depth) if tag.is_block_end() => skipping = Some(depth - 1),
+            Some(_) => {}
+            None => {
+                if let Some(replacement) = tag.block_start().and_then(|name| overrides.get(name)) {
+                    merged.push_str(&base[cursor..tag.span.end]);
+                    merged.push_str(replacement);
+                    skipping = Some(0);
+                }
+            }
+        }
+    }
+    merged.push_str(&base[cursor..]);
+    Ok(merged)
+}
+
+/// Offset of the first `@[block name]@` in `content` that no `@[end]@` closes
+fn unclosed_block(content: &str, delimiters: &Delimiters) -> Option<usize> {
+    scan_tags(content, delimiters)
+        .iter()
+        .find(|tag| tag.is_unclosed_block())
+        .map(|tag| tag.span.start)
+}
+
+/// Prefix of a tag declaring the file a template inherits its blocks from
+const EXTENDS_DIRECTIVE: &str = "extends ";
+
+/// Prefix of a tag opening an overridable block, closed by `@[end]@`
+const BLOCK_PREFIX: &str = "block ";
+
+/// Tag closing the innermost open block
+const END_BLOCK: &str = "end";
+
+/// Prefix of a placeholder that a `TronAssembler` fills with the rendered output of
+/// the member given that name with `TronRef::with_name`
+///
+/// `@[member:header]@` is an ordinary placeholder outside an assembler, and a
+/// value set on it explicitly takes precedence over the member's output.
+const MEMBER_PREFIX: &str = "member:";
+
+/// Prefix of a tag opening a named section, closed by `@[endsection]@`
+const SECTION_PREFIX: &str = "section:";
+
+/// Tag closing the innermost open section
+const END_SECTION: &str = "endsection";
+
+/// Prefix of a tag read from an environment variable at render time
+///
+/// `@[env:HOME]@` renders the value of `HOME` and fails with
+/// `TronError::MissingEnvVar` if it is not set, while `@[env:HOME:/root]@`
+/// falls back to `/root`. Like magic placeholders, these are never registered
+/// as regular placeholders.
+const ENV_PREFIX: &str = "env:";
+
+/// How line endings are normalized in template content and inserted values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    /// Leave line endings exactly as written
+    #[default]
+    Keep,
+    /// Convert every line ending to `\n`
+    Lf,
+    /// Convert every line ending to `\r\n`
+    CrLf,
+}
+
+impl LineEnding {
+    /// Apply this line ending policy to `text`
+    pub fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            LineEnding::Keep => Cow::Borrowed(text),
+            LineEnding::Lf if !text.contains('\r') => Cow::Borrowed(text),
+            LineEnding::Lf => Cow::Owned(text.replace("\r\n", "\n")),
+            LineEnding::CrLf => Cow::Owned(text.replace("\r\n", "\n").replace('\n', "\r\n")),
+        }
+    }
+}
+
+/// How substituted values are escaped by `TronTemplate::render_escaped`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EscapeMode {
+    /// Insert values exactly as `render` does
+    #[default]
+    None,
+    /// Replace `<`, `>`, `&`, `"` and `'` with HTML character references
+    Html,
+}
+
+impl EscapeMode {
+    /// Apply this escaping to `text`
+    pub fn escape<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            EscapeMode::Html if text.contains(['<', '>', '&', '"', '\'']) => {
+                let mut escaped = String::with_capacity(text.len() + 8);
+                for c in text.chars() {
+                    match c {
+                        '<' => escaped.push_str("&lt;"),
+                        '>' => escaped.push_str("&gt;"),
+                        '&' => escaped.push_str("&amp;"),
+                        '"' => escaped.push_str("&quot;"),
+                        '\'' => escaped.push_str("&#39;"),
+                        c => escaped.push(c),
+                    }
+                }
+                Cow::Owned(escaped)
+            }
+            _ => Cow::Borrowed(text),
+        }
+    }
+}
+
+/// Somewhere placeholder values can be looked up by name at render time
+///
+/// Implemented for `HashMap<String, String>`, for closures of the form
+/// `Fn(&str) -> Option<String>`, and by `EnvSource` for environment variables.
+/// Implement it to back values with a config system, database or secrets store.
+pub trait ValueSource {
+    /// The value for `name`, or `None` if this source does not have one
+    fn get(&self, name: &str) -> Option<String>;
+}
+
+impl ValueSource for HashMap<String, String> {
+    fn get(&self, name: &str) -> Option<String> {
+        HashMap::get(self, name).cloned()
+    }
+}
+
+impl<F> ValueSource for F
+where
+    F: Fn(&str) -> Option<String>,
+{
+    fn get(&self, name: &str) -> Option<String> {
+        self(name)
+    }
+}
+
+/// Reads each placeholder from the environment variable of the same name
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EnvSource;
+
+impl ValueSource for EnvSource {
+    fn get(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+}
+
+/// Resolves placeholder values on demand while a template renders
+///
+/// Every `ValueSource` is also a resolver. Implement this directly for lazy
+/// lookups that are only worth doing for placeholders that are actually used.
+pub trait PlaceholderResolver {
+    /// The value for `name`, or `None` if it cannot be resolved
+    fn resolve(&self, name: &str) -> Option<String>;
+}
+
+impl<T: ValueSource> PlaceholderResolver for T {
+    fn resolve(&self, name: &str) -> Option<String> {
+        self.get(name)
+    }
+}
+
+/// Adapts a `PlaceholderResolver` to the `ValueSource` used while rendering
+struct ResolverSource<'a>(&'a dyn PlaceholderResolver);
+
+impl ValueSource for ResolverSource<'_> {
+    fn get(&self, name: &str) -> Option<String> {
+        self.0.resolve(name)
+    }
+}
+
+/// Deepest nesting of `set_ref` calls a `TronRef` accepts unless configured otherwise
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
 /// A reference to a template that can be executed or composed
+///
+/// Cloning produces a fully independent copy: values, dependencies and
+/// settings can be changed on either side without affecting the other.
+/// `set_ref` renders the inserted reference immediately, so composed content
+/// is a plain value and no child references are shared between clones.
+/// Defaults registered with `set_ref_default` are owned copies as well.
 #[derive(Debug, Clone)]
 pub struct TronRef {
     template: TronTemplate,
     dependencies: Vec<String>,
+    name: Option<String>,
+    /// Longest chain of references composed into this one
+    depth: usize,
+    max_depth: usize,
+    /// References rendered into placeholders that are still unset at render time
+    default_refs: HashMap<String, TronRef>,
+    /// Dependency specs paired with the flag that must be enabled to include them
+    conditional_dependencies: Vec<(String, String)>,
+    /// Flags set with `set_flag`, separate from the template's placeholders
+    flags: HashMap<String, bool>,
+    /// Number of templates composed into each placeholder filled by `set_ref`
+    /// and the depth they reach, with a hash of the value it was filled with
+    composed: HashMap<String, (usize, usize, u64)>,
+}
+
+/// Shape of a composition tree, as reported by `TronRef::composition_stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompositionStats {
+    /// Longest chain of references below the root; 0 for a lone template
+    pub depth: usize,
+    /// Number of templates taking part, the root included
+    pub node_count: usize,
 }
 
 impl TronRef {
@@ -32,15 +630,113 @@ impl TronRef {
         Self {
             template,
             dependencies: Vec::new(),
+            name: None,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            default_refs: HashMap::new(),
+            conditional_dependencies: Vec::new(),
+            flags: HashMap::new(),
+            composed: HashMap::new(),
+        }
+    }
+
+    /// Limit how deeply references may be nested into this one, `DEFAULT_MAX_DEPTH` by default
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// How many levels of references are composed into this one; 0 if none
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Depth and size of the tree of references composed into this one
+    ///
+    /// Counts every template composed with `set_ref` and its variants whose
+    /// output is still the placeholder's value, plus the `set_ref_default`
+    /// defaults currently in use. A placeholder that is composed into again
+    /// only counts its latest value, and one changed or reset by any other
+    /// means no longer counts. The depth is taken over the same templates, so
+    /// it can be lower than `depth()`, which keeps the deepest nesting ever
+    /// composed to enforce the maximum depth.
+    pub fn composition_stats(&self) -> CompositionStats {
+        let mut stats = CompositionStats {
+            depth: 0,
+            node_count: 1,
+        };
+        for (placeholder, &(count, depth, hash)) in &self.composed {
+            let value = self.template.placeholders.get(placeholder);
+            if value.is_some_and(|value| fnv1a(value.as_bytes()) == hash) {
+                stats.node_count += count;
+                stats.depth = stats.depth.max(depth);
+            }
+        }
+        for (placeholder, default) in &self.default_refs {
+            if self.uses_default_ref(placeholder) {
+                let default = default.composition_stats();
+                stats.node_count += default.node_count;
+                stats.depth = stats.depth.max(default.depth + 1);
+            }
         }
+        stats
+    }
+
+    /// Give the reference a name, used to identify it within an assembler
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// The name given with `with_name`, if any
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
     }
 
     /// Add a dependency that will be included in rust-script execution
+    ///
+    /// Adding a spec that is already present has no effect.
     pub fn with_dependency(mut self, dependency: &str) -> Self {
-        self.dependencies.push(dependency.to_string());
+        self.add_dependency(dependency);
+        self
+    }
+
+    /// Add several dependencies at once, skipping any already present
+    pub fn with_dependencies<I, S>(mut self, dependencies: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for dependency in dependencies {
+            self.add_dependency(dependency.as_ref());
+        }
+        self
+    }
+
+    /// Add a dependency that is only included while `flag` is enabled with `set_flag`
+    ///
+    /// `with_conditional_dependency("serde = \"1\"", "use_serde")` only lists
+    /// serde once `set_flag("use_serde", true)` is called. Flags are not
+    /// placeholders and never appear in the rendered output. The flag is
+    /// checked whenever dependencies are read: by execution, manifest
+    /// generation and when this reference is composed into another.
+    pub fn with_conditional_dependency(mut self, dependency: &str, flag: &str) -> Self {
+        self.conditional_dependencies
+            .push((dependency.to_string(), flag.to_string()));
         self
     }
 
+    /// Enable or disable a flag checked by `with_conditional_dependency`
+    pub fn set_flag(&mut self, flag: &str, enabled: bool) {
+        self.flags.insert(flag.to_string(), enabled);
+    }
+
+    fn add_dependency(&mut self, dependency: &str) {
+        if !self.dependencies.iter().any(|existing| existing == dependency) {
+            self.dependencies.push(dependency.to_string());
+        }
+    }
+
     /// Get a reference to the inner template
     pub fn inner(&self) -> &TronTemplate {
         &self.template
@@ -56,16 +752,118 @@ impl TronRef {
         self.template.set(placeholder, value)
     }
 
+    /// Transform the inner template, keeping this reference's name and dependencies
+    pub fn map_template<F>(mut self, f: F) -> Result<TronRef>
+    where
+        F: FnOnce(TronTemplate) -> Result<TronTemplate>,
+    {
+        self.template = f(self.template)?;
+        Ok(self)
+    }
+
     /// Set a placeholder to use another template
+    ///
+    /// Fails with `TronError::DepthExceeded` if nesting `template_ref` here
+    /// would make this reference deeper than its maximum depth.
     pub fn set_ref(&mut self, placeholder: &str, template_ref: TronRef) -> Result<()> {
+        self.insert_ref(placeholder, template_ref, "")
+    }
+
+    /// Set a placeholder to a bare template, for composition without dependencies
+    ///
+    /// Equivalent to `set_ref` with the template wrapped in a new `TronRef`.
+    pub fn set_ref_template(&mut self, placeholder: &str, template: &TronTemplate) -> Result<()> {
+        self.insert_ref(placeholder, TronRef::new(template.clone()), "")
+    }
+
+    /// Compose `child` into `placeholder` if the template has it, like `set_ref`
+    ///
+    /// Returns `Ok(false)` without touching the template or its dependencies
+    /// when the placeholder does not exist. Failures while composing, such as
+    /// the child not rendering, are still reported as errors.
+    pub fn set_ref_if_present(&mut self, placeholder: &str, child: TronRef) -> Result<bool> {
+        if !self.template.placeholders.contains_key(placeholder) {
+            return Ok(false);
+        }
+        self.set_ref(placeholder, child)?;
+        Ok(true)
+    }
+
+    /// Set a placeholder to use another template, indented to match the placeholder's line
+    ///
+    /// Every line of the rendered child after the first is prefixed with the
+    /// leading whitespace of the line the placeholder sits on, so a multiline
+    /// body inserted at `    @[body]@` stays aligned. Blank lines are left
+    /// empty. If the placeholder occurs more than once, its first occurrence
+    /// decides the indentation.
+    pub fn set_ref_indented(&mut self, placeholder: &str, template_ref: TronRef) -> Result<()> {
+        let content = &self.template.content;
+        let indent = self
+            .template
+            .placeholder_uses(placeholder)
+            .first()
+            .map(|span| {
+                let line_start = content[..span.start].rfind('\n').map_or(0, |i| i + 1);
+                let line = &content[line_start..span.start];
+                line[..line.len() - line.trim_start_matches([' ', '\t']).len()].to_string()
+            })
+            .unwrap_or_default();
+        self.insert_ref(placeholder, template_ref, &indent)
+    }
+
+    /// Register a reference to render into `placeholder` whenever it has no value of its own
+    ///
+    /// Unlike `set_ref`, the default is only rendered at render time and only
+    /// if the placeholder is still unset then, so a later `set` or `set_ref`
+    /// overrides it. Its dependencies are likewise only included while it is
+    /// in use. Defaults are applied by this reference's `render`,
+    /// `render_with_context` and execution, not by the inner template, so
+    /// `inner().render_partial()` still shows such a placeholder as unset.
+    pub fn set_ref_default(&mut self, placeholder: &str, default: TronRef) -> Result<()> {
+        if !self.template.placeholders.contains_key(placeholder) {
+            return Err(TronError::MissingPlaceholder(placeholder.to_string()));
+        }
+        let depth = self.checked_depth(&default)?;
+        self.default_refs.insert(placeholder.to_string(), default);
+        self.depth = self.depth.max(depth);
+        Ok(())
+    }
+
+    /// The depth this reference reaches by nesting `template_ref`, if within its maximum
+    fn checked_depth(&self, template_ref: &TronRef) -> Result<usize> {
+        let depth = template_ref.depth + 1;
+        if depth > self.max_depth {
+            return Err(TronError::DepthExceeded {
+                depth,
+                limit: self.max_depth,
+            });
+        }
+        Ok(depth)
+    }
+
+    /// Render `template_ref` into `placeholder`, indenting every line after the first by `indent`
+    fn insert_ref(&mut self, placeholder: &str, template_ref: TronRef, indent: &str) -> Result<()> {
+        let depth = self.checked_depth(&template_ref)?;
+
         // First render the template we're inserting
-        let rendered = template_ref.template.render()?;
+        let mut rendered = template_ref.render()?;
+        if !indent.is_empty() {
+            rendered = indent_lines(&rendered, indent);
+        }
         
         // Set the rendered content as the placeholder value
         self.template.set(placeholder, &rendered)?;
+        let stats = template_ref.composition_stats();
+        self.composed.insert(
+            placeholder.to_string(),
+            (stats.node_count, stats.depth + 1, fnv1a(rendered.as_bytes())),
+        );
 
         // Merge dependencies
-        self.dependencies.extend(template_ref.dependencies);
+        for dependency in template_ref.active_dependencies() {
+            self.add_dependency(dependency);
+        }
+        self.depth = self.depth.max(depth);
         
         Ok(())
     }
@@ -73,132 +871,2923 @@ impl TronRef {
     /// Execute the template with rust-script
     #[cfg(feature = "execute")]
     pub async fn execute(&self) -> Result<String> {
-        use std::process::Command;
-        use tempfile::NamedTempFile;
-        use std::io::Write;
-        use which::which;
-
-        which("rust-script").map_err(|_| {
-            TronError::ExecutionError("rust-script not found. Install with: cargo install rust-script".into())
-        })?;
+        self.execution().run().await
+    }
 
-        let rendered = self.template.render()?;
-        let mut temp_file = NamedTempFile::new()
-            .map_err(|e| TronError::ExecutionError(format!("Failed to create temp file: {}", e)))?;
-        
-        let mut script_content = String::new();
-        for dep in &self.dependencies {
-            script_content.push_str(&format!("//! ```cargo\n//! [dependencies]\n//! {} \n//! ```\n", dep));
-        }
-        script_content.push_str(&rendered);
+    /// Execute the template with rust-script, yielding its output line by line as it arrives
+    ///
+    /// See `ExecuteOptions::run_streaming`.
+    #[cfg(feature = "execute")]
+    pub fn execute_streaming(&self) -> Result<impl Iterator<Item = Result<String>>> {
+        self.execution().run_streaming()
+    }
 
-        temp_file.write_all(script_content.as_bytes())
-            .map_err(|e| TronError::ExecutionError(format!("Failed to write temp file: {}", e)))?;
+    /// Configure arguments, input and environment before executing with rust-script
+    #[cfg(feature = "execute")]
+    pub fn execution(&self) -> ExecuteOptions<'_> {
+        ExecuteOptions::new(self)
+    }
 
-        let output = Command::new("rust-script")
-            .arg(temp_file.path())
-            .output()
-            .map_err(|e| TronError::ExecutionError(format!("Failed to execute script: {}", e)))?;
+    /// Render the template to a string
+    pub fn render(&self) -> Result<String> {
+        self.render_with_fallbacks(None)
+    }
 
-        if !output.status.success() {
-            return Err(TronError::ExecutionError(
-                String::from_utf8_lossy(&output.stderr).into_owned()
-            ));
+    /// Render and write the output to `path`, creating any missing parent directories
+    ///
+    /// An existing file is overwritten. Nothing is written if rendering fails.
+    pub fn render_to_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let rendered = self.render()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
         }
-
-        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        fs::write(path, rendered)?;
+        Ok(())
     }
 
-    /// Render the template to a string
-    pub fn render(&self) -> Result<String> {
-        self.template.render()
+    /// Render with values from `ctx` filling any placeholder not set on the template
+    ///
+    /// Values already set take precedence over the context, and the reference
+    /// itself is left unchanged, so it can be rendered under several contexts.
+    pub fn render_with_context(&self, ctx: &HashMap<String, String>) -> Result<String> {
+        self.render_with_fallbacks(Some(ctx))
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct TronTemplate {
-    content: String,
-    placeholders: HashMap<String, String>,
-    path: Option<PathBuf>,
-}
+    /// Render, filling unset placeholders from `source` and then from the default references
+    fn render_with_fallbacks(&self, source: Option<&dyn ValueSource>) -> Result<String> {
+        let lookup = |name: &str| source.and_then(|source| source.get(name)).filter(|v| !v.is_empty());
+        let mut defaults = HashMap::new();
+        for (placeholder, default) in &self.default_refs {
+            if self.uses_default_ref(placeholder) && lookup(placeholder).is_none() {
+                defaults.insert(placeholder.as_str(), default.render()?);
+            }
+        }
 
-impl TronTemplate {
-    /// Create a new template from a string
-    pub fn new(content: &str) -> Result<Self> {
-        let placeholders = Self::extract_placeholders(content)?;
-        Ok(Self {
-            content: content.to_string(),
-            placeholders,
-            path: None,
-        })
+        match (source, defaults.is_empty()) {
+            (None, true) => self.template.render(),
+            _ => self
+                .template
+                .render_with_source(&|name: &str| lookup(name).or_else(|| defaults.get(name).cloned())),
+        }
     }
 
-    /// Load a template from a file
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let content = fs::read_to_string(&path)?;
-        let mut template = Self::new(&content)?;
-        template.path = Some(path.as_ref().to_path_buf());
-        Ok(template)
+    /// Whether the default reference for `placeholder` renders in its place
+    ///
+    /// Only while the placeholder still exists and has no value; a default
+    /// whose placeholder was removed from the content is ignored.
+    fn uses_default_ref(&self, placeholder: &str) -> bool {
+        self.template
+            .placeholders
+            .get(placeholder)
+            .is_some_and(String::is_empty)
     }
 
-    fn extract_placeholders(content: &str) -> Result<HashMap<String, String>> {
-        let mut placeholders = HashMap::new();
-        let pattern = regex::Regex::new(r"@\[([^]]+)\]@").unwrap();
-        
-        for capture in pattern.captures_iter(content) {
-            let placeholder = capture.get(1).unwrap().as_str().trim();
-            placeholders.insert(placeholder.to_string(), String::new());
+    /// Own dependencies, then conditional ones whose flag is enabled, then those of
+    /// every default reference currently in use
+    fn active_dependencies(&self) -> Vec<&str> {
+        let mut dependencies: Vec<&str> = self.dependencies.iter().map(String::as_str).collect();
+        for (dependency, flag) in &self.conditional_dependencies {
+            let enabled = self.flags.get(flag).copied().unwrap_or(false);
+            if enabled && !dependencies.contains(&dependency.as_str()) {
+                dependencies.push(dependency);
+            }
         }
-        
-        Ok(placeholders)
+        for (placeholder, default) in &self.default_refs {
+            if self.uses_default_ref(placeholder) {
+                for dependency in default.active_dependencies() {
+                    if !dependencies.contains(&dependency) {
+                        dependencies.push(dependency);
+                    }
+                }
+            }
+        }
+        dependencies
     }
 
-    /// Set a placeholder value
-    pub fn set(&mut self, placeholder: &str, value: &str) -> Result<()> {
-        if !self.placeholders.contains_key(placeholder) {
-            return Err(TronError::MissingPlaceholder(placeholder.to_string()));
+    /// Generate a minimal `Cargo.toml` for a crate using this template's dependencies
+    ///
+    /// Fails if two dependency specs name the same crate with different requirements.
+    pub fn to_cargo_manifest(&self, package_name: &str) -> Result<String> {
+        let mut manifest = format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n",
+            package_name
+        );
+        for dependency in self.checked_dependencies()? {
+            manifest.push_str(dependency);
+            manifest.push('\n');
         }
-        self.placeholders.insert(placeholder.to_string(), value.to_string());
-        Ok(())
+        Ok(manifest)
     }
 
-    /// Render the template
-    pub fn render(&self) -> Result<String> {
-        let mut result = self.content.clone();
-        
-        for (placeholder, value) in &self.placeholders {
-            let pattern = format!("@[{}]@", placeholder);
-            if value.is_empty() {
-                return Err(TronError::MissingPlaceholder(placeholder.clone()));
+    /// The dependency specs, verified to name each crate at most once
+    fn checked_dependencies(&self) -> Result<Vec<&str>> {
+        let dependencies = self.active_dependencies();
+        let mut crates: HashMap<&str, &str> = HashMap::new();
+        for &dependency in &dependencies {
+            let (name, _) = dependency.split_once('=').ok_or_else(|| {
+                TronError::Parse(format!("Invalid dependency spec: {}", dependency))
+            })?;
+            let name = name.trim();
+            if let Some(existing) = crates.insert(name, dependency) {
+                return Err(TronError::Parse(format!(
+                    "Conflicting dependency specs for `{}`: {} and {}",
+                    name, existing, dependency
+                )));
             }
-            result = result.replace(&pattern, value);
         }
-        
-        Ok(result)
+        Ok(dependencies)
     }
 }
 
-/// Assemble multiple templates together
-#[derive(Debug)]
-pub struct TronAssembler {
-    templates: Vec<TronRef>,
+/// Options for running a `TronRef` with rust-script, created by `TronRef::execution`
+#[cfg(feature = "execute")]
+#[derive(Debug, Clone)]
+pub struct ExecuteOptions<'a> {
+    template_ref: &'a TronRef,
+    process: ProcessOptions,
+    edition: Option<String>,
+    debug_script: bool,
 }
 
-impl TronAssembler {
-    pub fn new() -> Self {
+/// How the rust-script process is started, owned so it can move to another thread
+#[cfg(feature = "execute")]
+#[derive(Debug, Clone, Default)]
+struct ProcessOptions {
+    args: Vec<String>,
+    stdin: Option<String>,
+    env: Vec<(String, String)>,
+    current_dir: Option<PathBuf>,
+    timeout: Option<std::time::Duration>,
+    cache: bool,
+    runner: Option<PathBuf>,
+}
+
+#[cfg(feature = "execute")]
+impl<'a> ExecuteOptions<'a> {
+    fn new(template_ref: &'a TronRef) -> Self {
         Self {
-            templates: Vec::new(),
+            template_ref,
+            process: ProcessOptions::default(),
+            edition: None,
+            debug_script: false,
         }
     }
 
-    /// Add a template reference to the assembler
-    pub fn add_template(&mut self, template: TronRef) {
-        self.templates.push(template);
+    /// Pass a command line argument to the script
+    pub fn with_arg(mut self, arg: &str) -> Self {
+        self.process.args.push(arg.to_string());
+        self
     }
 
-    /// Set a value for a placeholder across all templates
-    pub fn set_global(&mut self, placeholder: &str, value: &str) -> Result<()> {
-        for template in &mut self.templates {
+    /// Pass several command line arguments to the script
+    pub fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.process.args.extend(args.into_iter().map(|arg| arg.as_ref().to_string()));
+        self
+    }
+
+    /// Feed text to the script's standard input
+    pub fn with_stdin(mut self, input: &str) -> Self {
+        self.process.stdin = Some(input.to_string());
+        self
+    }
+
+    /// Set an environment variable for the script
+    pub fn with_env(mut self, key: &str, value: &str) -> Self {
+        self.process.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Run the script from the given working directory
+    pub fn with_current_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.process.current_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Kill the script and fail if it runs for longer than `timeout`
+    ///
+    /// The script is polled until it exits; `run` does this off the executor.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.process.timeout = Some(timeout);
+        self
+    }
+
+    /// Compile the script with a specific Rust edition, e.g. `"2021"`
+    pub fn with_edition(mut self, edition: &str) -> Self {
+        self.edition = Some(edition.to_string());
+        self
+    }
+
+    /// Attach the generated script to execution errors as `TronError::ScriptFailed`
+    pub fn with_debug_script(mut self, enabled: bool) -> Self {
+        self.debug_script = enabled;
+        self
+    }
+
+    /// Reuse a stable script path for identical scripts so rust-script can skip recompiling
+    ///
+    /// Scripts are written under the system temp directory, named by a hash of
+    /// the full script including its dependency header. Running the same
+    /// rendered content and dependencies again points rust-script at the same
+    /// unchanged file, so its own compilation cache is reused.
+    pub fn with_cache(mut self, enabled: bool) -> Self {
+        self.process.cache = enabled;
+        self
+    }
+
+    /// Run scripts with the given rust-script binary instead of looking it up on `PATH`
+    pub fn with_runner<P: AsRef<Path>>(mut self, runner: P) -> Self {
+        self.process.runner = Some(runner.as_ref().to_path_buf());
+        self
+    }
+
+    /// Where a cached script with this content is stored
+    fn cached_script_path(script: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join("tron-script-cache")
+            .join(format!("{:016x}.rs", fnv1a(script.as_bytes())))
+    }
+
+    /// The script exactly as it is handed to rust-script, manifest header included
+    pub fn script(&self) -> Result<String> {
+        let rendered = self.template_ref.render()?;
+        let dependencies = self.template_ref.checked_dependencies()?;
+
+        let mut manifest = Vec::new();
+        if let Some(edition) = &self.edition {
+            manifest.push("[package]".to_string());
+            manifest.push(format!("edition = \"{}\"", edition));
+        }
+        if !dependencies.is_empty() {
+            manifest.push("[dependencies]".to_string());
+            manifest.extend(dependencies.into_iter().map(String::from));
+        }
+
+        let mut script = String::new();
+        if !manifest.is_empty() {
+            script.push_str("//! ```cargo\n");
+            for line in manifest {
+                script.push_str(&format!("//! {}\n", line));
+            }
+            script.push_str("//! ```\n");
+        }
+        script.push_str(&rendered);
+        Ok(script)
+    }
+
+    /// Execute the script and return its standard output
+    ///
+    /// The script runs and is waited for on a thread of its own, so awaiting
+    /// this never blocks the executor, timeout included.
+    pub async fn run(&self) -> Result<String> {
+        let script = self.script()?;
+        let process = self.process.clone();
+        let debug_script = self.debug_script;
+        run_on_thread(move || {
+            process
+                .run_script(&script)
+                .map_err(|error| attach_script(error, debug_script.then_some(script.as_str())))
+        })
+        .await
+    }
+
+    /// Execute the script on the current thread and return its standard output
+    pub fn run_blocking(&self) -> Result<String> {
+        let script = self.script()?;
+        self.process
+            .run_script(&script)
+            .map_err(|error| self.attach_script(error, &script))
+    }
+
+    /// Execute the script and yield its standard output line by line as it is produced
+    ///
+    /// Lines come without their line endings. If the script exits unsuccessfully
+    /// the last item is the resulting error. The timeout option is not applied
+    /// while streaming. Dropping the iterator early kills the script.
+    pub fn run_streaming(&self) -> Result<impl Iterator<Item = Result<String>>> {
+        use std::io::{BufRead, BufReader};
+
+        let script = self.script()?;
+        let mut running = self
+            .process
+            .spawn(&script)
+            .map_err(|error| self.attach_script(error, &script))?;
+        let stdout = running.child.stdout.take().ok_or_else(|| {
+            TronError::ExecutionError("Failed to capture script output".into())
+        })?;
+        let stderr = read_pipe(running.child.stderr.take());
+
+        Ok(ScriptLines {
+            running,
+            lines: BufReader::new(stdout).lines(),
+            stderr: Some(stderr),
+            script: self.debug_script.then_some(script),
+            finished: false,
+        })
+    }
+
+    /// Turn an execution error into `ScriptFailed` when debugging scripts
+    fn attach_script(&self, error: TronError, script: &str) -> TronError {
+        attach_script(error, self.debug_script.then_some(script))
+    }
+}
+
+#[cfg(feature = "execute")]
+impl ProcessOptions {
+    fn run_script(&self, script: &str) -> Result<String> {
+        use std::thread;
+        use std::time::Instant;
+
+        let mut running = self.spawn(script)?;
+        let child = &mut running.child;
+        let stdout = read_pipe(child.stdout.take());
+        let stderr = read_pipe(child.stderr.take());
+
+        let started = Instant::now();
+        let status = loop {
+            let finished = match self.timeout {
+                None => child.wait().map(Some),
+                Some(_) => child.try_wait(),
+            }
+            .map_err(|e| TronError::ExecutionError(format!("Failed to wait for script: {}", e)))?;
+            if let Some(status) = finished {
+                break status;
+            }
+            if let Some(timeout) = self.timeout.filter(|timeout| started.elapsed() >= *timeout) {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(TronError::ExecutionError(format!(
+                    "Script timed out after {:?}",
+                    timeout
+                )));
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        };
+
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+
+        if !status.success() {
+            return Err(TronError::ProcessFailed {
+                command: running.command,
+                status: status.code(),
+                stderr: String::from_utf8_lossy(&stderr).into_owned(),
+            });
+        }
+
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    }
+
+    /// Write the script where rust-script can read it and start rust-script on it
+    fn spawn(&self, script: &str) -> Result<RunningScript> {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+        use std::thread;
+        use tempfile::NamedTempFile;
+        use which::which;
+
+        let runner = match &self.runner {
+            Some(runner) => runner.clone(),
+            None => which("rust-script").map_err(|_| {
+                TronError::ExecutionError("rust-script not found. Install with: cargo install rust-script".into())
+            })?,
+        };
+
+        let mut script_file = None;
+        let script_path = if self.cache {
+            let path = ExecuteOptions::cached_script_path(script);
+            if fs::read_to_string(&path).ok().as_deref() != Some(script) {
+                fs::create_dir_all(path.parent().unwrap_or(Path::new("")))
+                    .and_then(|_| fs::write(&path, script))
+                    .map_err(|e| TronError::ExecutionError(format!("Failed to write cached script: {}", e)))?;
+            }
+            path
+        } else {
+            let file = script_file.insert(NamedTempFile::new()
+                .map_err(|e| TronError::ExecutionError(format!("Failed to create temp file: {}", e)))?);
+            file.write_all(script.as_bytes())
+                .map_err(|e| TronError::ExecutionError(format!("Failed to write temp file: {}", e)))?;
+            file.path().to_path_buf()
+        };
+
+        let mut command = Command::new(runner);
+        command
+            .arg(&script_path)
+            .args(&self.args)
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .stdin(if self.stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| TronError::ExecutionError(format!("Failed to execute script: {}", e)))?;
+
+        if let (Some(input), Some(mut pipe)) = (self.stdin.clone(), child.stdin.take()) {
+            thread::spawn(move || pipe.write_all(input.as_bytes()));
+        }
+
+        let command = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|part| part.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(RunningScript {
+            child,
+            command,
+            _script_file: script_file,
+        })
+    }
+}
+
+/// A rust-script process started by `ExecuteOptions`
+#[cfg(feature = "execute")]
+struct RunningScript {
+    child: std::process::Child,
+    /// The command line, for error messages
+    command: String,
+    /// Keeps an uncached script alive until rust-script has finished with it
+    _script_file: Option<tempfile::NamedTempFile>,
+}
+
+/// Iterator over the output lines of a script started by `ExecuteOptions::run_streaming`
+#[cfg(feature = "execute")]
+struct ScriptLines {
+    running: RunningScript,
+    lines: std::io::Lines<std::io::BufReader<std::process::ChildStdout>>,
+    stderr: Option<std::thread::JoinHandle<Vec<u8>>>,
+    /// The generated script, kept only when it should be attached to errors
+    script: Option<String>,
+    finished: bool,
+}
+
+#[cfg(feature = "execute")]
+impl ScriptLines {
+    /// Wait for the script to exit once its output is exhausted
+    fn finish(&mut self) -> Result<()> {
+        let status = self
+            .running
+            .child
+            .wait()
+            .map_err(|e| TronError::ExecutionError(format!("Failed to wait for script: {}", e)))?;
+        let stderr = self
+            .stderr
+            .take()
+            .map(|stderr| stderr.join().unwrap_or_default())
+            .unwrap_or_default();
+
+        if !status.success() {
+            return Err(TronError::ProcessFailed {
+                command: self.running.command.clone(),
+                status: status.code(),
+                stderr: String::from_utf8_lossy(&stderr).into_owned(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "execute")]
+impl Iterator for ScriptLines {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let error = match self.lines.next() {
+            Some(Ok(line)) => return Some(Ok(line)),
+            Some(Err(e)) => {
+                let _ = self.running.child.kill();
+                let _ = self.running.child.wait();
+                TronError::ExecutionError(format!("Failed to read script output: {}", e))
+            }
+            None => self.finish().err()?,
+        };
+        self.finished = true;
+        Some(Err(attach_script(error, self.script.as_deref())))
+    }
+}
+
+#[cfg(feature = "execute")]
+impl Drop for ScriptLines {
+    fn drop(&mut self) {
+        if !self.finished && matches!(self.running.child.try_wait(), Ok(None)) {
+            let _ = self.running.child.kill();
+            let _ = self.running.child.wait();
+        }
+    }
+}
+
+/// Shared between `run_on_thread` and the thread it starts
+#[cfg(feature = "execute")]
+struct ThreadState<T> {
+    result: Option<T>,
+    waker: Option<std::task::Waker>,
+}
+
+/// Future completing with the result of work done on a thread of its own
+#[cfg(feature = "execute")]
+struct ThreadResult<T> {
+    state: Arc<std::sync::Mutex<ThreadState<T>>>,
+}
+
+#[cfg(feature = "execute")]
+impl<T> std::future::Future for ThreadResult<T> {
+    type Output = T;
+
+    fn poll(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<T> {
+        let mut state = self.state.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        match state.result.take() {
+            Some(result) => std::task::Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                std::task::Poll::Pending
+            }
+        }
+    }
+}
+
+/// Run `work` on a new thread, returning a future for its result
+///
+/// A panic in `work` completes the future with an `ExecutionError`.
+#[cfg(feature = "execute")]
+fn run_on_thread<F>(work: F) -> ThreadResult<Result<String>>
+where
+    F: FnOnce() -> Result<String> + Send + 'static,
+{
+    let state = Arc::new(std::sync::Mutex::new(ThreadState { result: None, waker: None }));
+    let shared = Arc::clone(&state);
+    std::thread::spawn(move || {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(work))
+            .unwrap_or_else(|_| {
+                Err(TronError::ExecutionError("Script runner thread panicked".into()))
+            });
+        let mut state = shared.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    });
+    ThreadResult { state }
+}
+
+/// Read everything from a child process pipe on a background thread
+#[cfg(feature = "execute")]
+fn read_pipe<R>(pipe: Option<R>) -> std::thread::JoinHandle<Vec<u8>>
+where
+    R: std::io::Read + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Wrap an execution error as `ScriptFailed` carrying `script`, when there is one
+#[cfg(feature = "execute")]
+fn attach_script(error: TronError, script: Option<&str>) -> TronError {
+    let Some(script) = script else {
+        return error;
+    };
+    match error {
+        TronError::ExecutionError(message) => TronError::ScriptFailed {
+            message,
+            script: script.to_string(),
+        },
+        TronError::ProcessFailed { command, status, stderr } => TronError::ScriptFailed {
+            message: process_failure(&command, status, &stderr),
+            script: script.to_string(),
+        },
+        other => other,
+    }
+}
+
+/// A template containing `@[name]@` placeholders
+///
+/// Placeholder names are trimmed, so `@[ name ]@` and `@[name]@` are the same
+/// placeholder. A `-` directly inside either delimiter trims whitespace from the
+/// output on that side of the placeholder: `@[- name]@` removes the spaces and
+/// tabs before it followed by at most one preceding line break, and `@[name -]@`
+/// removes the spaces and tabs after it followed by at most one line break.
+///
+/// Tags starting with `#`, like `@[# explain this section]@`, are comments and
+/// are removed from the rendered output.
+///
+/// A placeholder can declare a default used whenever it has no value, as in
+/// `@[port = 8080]@`.
+///
+/// An opening delimiter preceded by its own first character is escaped: `@@[`
+/// renders as a literal `@[` and never starts a placeholder. A run of `@`
+/// before an opening delimiter stands for half as many literal `@`, so
+/// `user@@@[host]@` renders `user@` followed by the value of `host`. Templates
+/// can opt into `\@[` escapes instead with `with_escape_style(EscapeStyle::Backslash)`,
+/// where `\\@[name]@` is likewise a backslash followed by a placeholder.
+#[derive(Clone)]
+pub struct TronTemplate {
+    /// Shared between clones, so cloning never copies the source text
+    content: Arc<str>,
+    placeholders: HashMap<String, String>,
+    path: Option<PathBuf>,
+    line_ending: LineEnding,
+    auto_escaped: HashSet<String>,
+    /// Placeholders set with `set_raw`, never escaped by `render_escaped`
+    raw: HashSet<String>,
+    validators: HashMap<String, Validator>,
+    delimiters: Delimiters,
+    /// Whether `set` silently ignores names that are not placeholders
+    ignore_unknown: bool,
+    /// Most occurrences filled for placeholders set with `set_limited`
+    limits: HashMap<String, usize>,
+    /// Canonical placeholder each alias takes its value from, keyed by alias
+    aliases: HashMap<String, String>,
+    /// Output of the last `render`, cleared by every method that changes it
+    rendered: OnceLock<String>,
+}
+
+/// Check run against a value before `set` accepts it
+type Validator = Arc<dyn Fn(&str) -> std::result::Result<(), String> + Send + Sync>;
+
+/// Which set values a render used, as reported by `TronTemplate::render_with_report`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RenderReport {
+    /// Placeholders whose set values were substituted, sorted
+    pub substituted: Vec<String>,
+    /// Placeholders with a set value that never made it into the output, sorted
+    pub unused: Vec<String>,
+}
+
+impl TronTemplate {
+    /// Create a new template from a string
+    ///
+    /// Fails on `@[include:...]@` and `@[raw_include:...]@` tags, which are
+    /// only expanded when loading from a file with `from_file`.
+    pub fn new(content: &str) -> Result<Self> {
+        Self::from_parts(content, Delimiters::default())
+    }
+
+    /// Create a new template from a string using custom placeholder delimiters
+    ///
+    /// For example `TronTemplate::with_delimiters("fn {{ name }}() {}", "{{", "}}")`.
+    /// The delimiters stay with the template through cloning and composition, so a
+    /// child using `{{ }}` can be composed into a parent using the default `@[ ]@`.
+    pub fn with_delimiters(content: &str, open: &str, close: &str) -> Result<Self> {
+        Self::from_parts(content, Delimiters::new(open, close)?)
+    }
+
+    fn from_parts(content: &str, delimiters: Delimiters) -> Result<Self> {
+        Self::parse(content, delimiters, false)
+    }
+
+    /// Create a template, skipping include tags instead of rejecting them if `includes` is set
+    fn parse(content: &str, delimiters: Delimiters, includes: bool) -> Result<Self> {
+        let placeholders = Self::extract_placeholders(content, &delimiters, includes)?;
+        Ok(Self {
+            content: Arc::from(content),
+            placeholders,
+            path: None,
+            line_ending: LineEnding::Keep,
+            auto_escaped: HashSet::new(),
+            raw: HashSet::new(),
+            validators: HashMap::new(),
+            delimiters,
+            ignore_unknown: false,
+            limits: HashMap::new(),
+            aliases: HashMap::new(),
+            rendered: OnceLock::new(),
+        })
+    }
+
+    /// Switch how opening delimiters are escaped, re-reading the content's placeholders
+    ///
+    /// Values already set are kept for placeholders that still exist under
+    /// the new style. Only one style is active at a time, so with
+    /// `EscapeStyle::Backslash` a doubled `@@[` is an `@` followed by a placeholder.
+    pub fn with_escape_style(mut self, style: EscapeStyle) -> Result<Self> {
+        self.delimiters.escape = style;
+        let mut placeholders = Self::extract_placeholders(&self.content, &self.delimiters, false)?;
+        for (name, value) in placeholders.iter_mut() {
+            if let Some(existing) = self.placeholders.remove(name) {
+                *value = existing;
+            }
+        }
+        self.placeholders = placeholders;
+        self.auto_escaped.retain(|name| self.placeholders.contains_key(name));
+        self.raw.retain(|name| self.placeholders.contains_key(name));
+        self.validators.retain(|name, _| self.placeholders.contains_key(name));
+        self.rendered.take();
+        Ok(self)
+    }
+
+    /// The escape convention for literal opening delimiters, `EscapeStyle::Double` by default
+    pub fn escape_style(&self) -> EscapeStyle {
+        self.delimiters.escape
+    }
+
+    /// The opening and closing placeholder delimiters, `("@[", "]@")` by default
+    pub fn delimiters(&self) -> (&str, &str) {
+        (&self.delimiters.open, &self.delimiters.close)
+    }
+
+    /// Load a template from a file
+    ///
+    /// `@[include:path]@` directives are replaced by the content of the named
+    /// file, resolved relative to the directory of the file containing them.
+    /// `@[raw_include:path]@` splices a file in verbatim, without looking for
+    /// placeholders or includes in it.
+    ///
+    /// A file starting with `@[extends base.tron]@` inherits from `base.tron`:
+    /// it renders as the base, with each `@[block name]@ ... @[end]@` the
+    /// file defines replacing the base's block of the same name. Blocks the
+    /// file does not override keep the base's default content.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::load(path.as_ref(), None, Delimiters::default())
+    }
+
+    /// Load a template from a file that uses custom placeholder delimiters
+    ///
+    /// Include directives in the file are written with the same delimiters,
+    /// e.g. `{{ include:header.tron }}`.
+    pub fn from_file_with_delimiters<P: AsRef<Path>>(path: P, open: &str, close: &str) -> Result<Self> {
+        Self::load(path.as_ref(), None, Delimiters::new(open, close)?)
+    }
+
+    /// Load a template from a file, resolving every `@[include:path]@` under `base_dir`
+    ///
+    /// Nested includes resolve under `base_dir` too, so a shared `templates/`
+    /// root can be used regardless of where each file lives.
+    pub fn from_file_with_base<P: AsRef<Path>, B: AsRef<Path>>(path: P, base_dir: B) -> Result<Self> {
+        Self::load(path.as_ref(), Some(base_dir.as_ref()), Delimiters::default())
+    }
+
+    fn load(path: &Path, base_dir: Option<&Path>, delimiters: Delimiters) -> Result<Self> {
+        let content = expand_includes(path, base_dir, &delimiters, &mut Vec::new())?;
+        let mut template = Self::from_parts(&content, delimiters)?;
+        template.path = Some(path.to_path_buf());
+        Ok(template)
+    }
+
+    /// Create a template from embedded content, recording `virtual_path` as its source
+    ///
+    /// Meant for `include_str!`: `TronTemplate::from_embedded(include_str!("main.tron"), "main.tron")`
+    /// renders `@[__file__]@` and `@[__template__]@` as if it had been loaded
+    /// from `main.tron`. Include directives are rejected as in `new`, since
+    /// nothing is read from disk.
+    pub fn from_embedded(content: &str, virtual_path: &str) -> Result<Self> {
+        Ok(Self::new(content)?.with_path(virtual_path))
+    }
+
+    /// Associate a source path with the template, as if it had been loaded from there
+    ///
+    /// The path is what `@[__file__]@` and `@[__template__]@` render as and is
+    /// shown in the template's debug output. Includes are not affected, since
+    /// they are only expanded when loading from a file.
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self.rendered.take();
+        self
+    }
+
+    /// Normalize line endings in the content and in every value inserted at render time
+    ///
+    /// Apply this right after loading, e.g. `TronTemplate::from_file(path)?.with_line_ending(LineEnding::Lf)`,
+    /// so generated output is consistent regardless of how the template was saved.
+    pub fn with_line_ending(mut self, line_ending: LineEnding) -> Self {
+        if let Cow::Owned(content) = line_ending.normalize(&self.content) {
+            self.content = content.into();
+        }
+        self.line_ending = line_ending;
+        self.rendered.take();
+        self
+    }
+
+    /// Placeholders declared in `content`, each with an empty value
+    ///
+    /// Include tags are an error unless `includes` is set, in which case
+    /// they are skipped like other markup.
+    fn extract_placeholders(
+        content: &str,
+        delimiters: &Delimiters,
+        includes: bool,
+    ) -> Result<HashMap<String, String>> {
+        let mut placeholders = HashMap::new();
+        
+        for tag in scan_tags(content, delimiters) {
+            let placeholder = tag.name();
+            if tag.renders_nothing() {
+                continue;
+            }
+            if placeholder.trim_start_matches(ENV_PREFIX).is_empty() {
+                return Err(parse_error_at(content, tag.span.start, "empty placeholder name"));
+            }
+            if placeholder.starts_with(INCLUDE_DIRECTIVE)
+                || placeholder.starts_with(RAW_INCLUDE_DIRECTIVE)
+            {
+                if includes {
+                    continue;
+                }
+                return Err(parse_error_at(
+                    content,
+                    tag.span.start,
+                    "includes are only expanded in templates loaded from a file",
+                ));
+            }
+            if MAGIC_PLACEHOLDERS.contains(&placeholder) || placeholder.starts_with(ENV_PREFIX) {
+                continue;
+            }
+            placeholders.insert(placeholder.to_string(), String::new());
+        }
+        
+        Ok(placeholders)
+    }
+
+    /// Set a placeholder value
+    ///
+    /// Fails with `TronError::InvalidValue` if a validator registered for the
+    /// placeholder rejects the value.
+    pub fn set(&mut self, placeholder: &str, value: &str) -> Result<()> {
+        if !self.placeholders.contains_key(placeholder) {
+            if self.ignore_unknown {
+                return Ok(());
+            }
+            return Err(TronError::MissingPlaceholder(placeholder.to_string()));
+        }
+        if let Some(validator) = self.validators.get(placeholder) {
+            validator(value).map_err(|message| TronError::InvalidValue {
+                placeholder: placeholder.to_string(),
+                message,
+            })?;
+        }
+        self.placeholders.insert(placeholder.to_string(), value.to_string());
+        self.raw.remove(placeholder);
+        self.limits.remove(placeholder);
+        self.rendered.take();
+        Ok(())
+    }
+
+    /// Set a placeholder only if it has no value yet, returning whether it was set
+    ///
+    /// A non-empty value is left untouched. Fails like `set` for unknown names
+    /// and values rejected by a validator.
+    pub fn set_if_empty(&mut self, placeholder: &str, value: &str) -> Result<bool> {
+        if self.placeholders.get(placeholder).is_some_and(|existing| !existing.is_empty()) {
+            return Ok(false);
+        }
+        self.set(placeholder, value)?;
+        Ok(self.placeholders.contains_key(placeholder))
+    }
+
+    /// Call `f` with every placeholder name, in sorted order, and set each one it returns a value for
+    ///
+    /// Placeholders for which `f` returns `None` keep their current value.
+    /// Stops at the first value `set` rejects; values set before it are kept.
+    pub fn apply<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&str) -> Option<String>,
+    {
+        let mut names: Vec<String> = self.placeholders.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            if let Some(value) = f(&name) {
+                self.set(&name, &value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Make `alias` render the value of `canonical` whenever it has no value of its own
+    ///
+    /// Both names must be placeholders of the template. Setting `canonical`
+    /// then fills both, while a value set on `alias` itself still takes
+    /// precedence. An alias follows a single canonical name; adding it again
+    /// replaces the earlier one.
+    pub fn add_alias(&mut self, canonical: &str, alias: &str) -> Result<()> {
+        for name in [canonical, alias] {
+            if !self.placeholders.contains_key(name) {
+                return Err(TronError::MissingPlaceholder(name.to_string()));
+            }
+        }
+        self.aliases.insert(alias.to_string(), canonical.to_string());
+        self.rendered.take();
+        Ok(())
+    }
+
+    /// Set a placeholder value that fills at most `max_occurrences` of its occurrences
+    ///
+    /// Occurrences are filled in content order; the ones past the limit are
+    /// left in the output exactly as written. A later `set` fills every
+    /// occurrence again.
+    pub fn set_limited(&mut self, name: &str, value: &str, max_occurrences: usize) -> Result<()> {
+        self.set(name, value)?;
+        if self.placeholders.contains_key(name) {
+            self.limits.insert(name.to_string(), max_occurrences);
+        }
+        Ok(())
+    }
+
+    /// Set a placeholder to a value that is already escaped
+    ///
+    /// Behaves like `set`, except that `render_escaped` inserts the value
+    /// as-is. A later `set` on the placeholder makes it escapable again.
+    pub fn set_raw(&mut self, placeholder: &str, value: &str) -> Result<()> {
+        self.set(placeholder, value)?;
+        if self.placeholders.contains_key(placeholder) {
+            self.raw.insert(placeholder.to_string());
+        }
+        Ok(())
+    }
+
+    /// Make `set` and `set_raw` succeed without effect for names that are not placeholders
+    ///
+    /// Off by default, so typos are caught. Enable it when applying one set of
+    /// values to templates that each use only some of them. `set_checked` and
+    /// the other setters stay strict.
+    pub fn ignore_unknown_sets(&mut self, enabled: bool) {
+        self.ignore_unknown = enabled;
+    }
+
+    /// Like `set`, but also failing if no occurrence of the placeholder would render the value
+    ///
+    /// Fails with `TronError::UnusedPlaceholder` for names that are registered
+    /// without being substituted anywhere, such as ones added by
+    /// `with_placeholder` that the content does not use. The value is not set
+    /// in that case.
+    pub fn set_checked(&mut self, placeholder: &str, value: &str) -> Result<()> {
+        if !self.placeholders.contains_key(placeholder) {
+            return Err(TronError::MissingPlaceholder(placeholder.to_string()));
+        }
+        let live = split_pieces(&self.content, &self.delimiters)
+            .iter()
+            .any(|piece| matches!(piece, Piece::Tag(tag) if tag.name() == placeholder));
+        if !live {
+            return Err(TronError::UnusedPlaceholder(placeholder.to_string()));
+        }
+        self.set(placeholder, value)
+    }
+
+    /// Whether the named placeholder currently has a non-empty value
+    ///
+    /// Fails with `TronError::MissingPlaceholder` if the template has no such placeholder.
+    pub fn is_placeholder_set(&self, name: &str) -> Result<bool> {
+        self.placeholders
+            .get(name)
+            .map(|value| !value.is_empty())
+            .ok_or_else(|| TronError::MissingPlaceholder(name.to_string()))
+    }
+
+    /// A new template with the given values baked into its content
+    ///
+    /// The named placeholders are substituted literally and are no longer
+    /// placeholders of the returned template, while every other placeholder
+    /// stays as written, keeping any value it already has. Baked values are
+    /// escaped, so delimiter-like text in them never becomes a new placeholder.
+    /// A value passed unchanged keeps any limit from `set_limited`, and the
+    /// occurrences past it are baked as the tag text `render` leaves there.
+    /// Fails like `set` on unknown names or values rejected by a validator.
+    pub fn with_partial(&self, values: &HashMap<String, String>) -> Result<TronTemplate> {
+        let mut bound = self.clone();
+        for (name, value) in values {
+            if bound.placeholders.get(name) != Some(value) {
+                bound.set(name, value)?;
+            }
+        }
+
+        let mut filled: HashMap<String, usize> = HashMap::new();
+        let content = self.baked_content(|tag| {
+            let canonical = bound.aliases.get(tag.name()).map_or(tag.name(), String::as_str);
+            if !values.contains_key(tag.name()) && !values.contains_key(canonical) {
+                return None;
+            }
+            if let Some(&limit) = bound.limits.get(tag.name()) {
+                let count = filled.entry(tag.name().to_string()).or_default();
+                *count += 1;
+                if *count > limit {
+                    return Some(Cow::Owned(self.content[tag.span.clone()].to_string()));
+                }
+            }
+            bound.value_for(tag.name())
+        });
+
+        let mut partial = Self::from_parts(&content, bound.delimiters.clone())?;
+        for (name, value) in partial.placeholders.iter_mut() {
+            if let Some(existing) = bound.placeholders.remove(name) {
+                *value = existing;
+            }
+        }
+        partial.path = bound.path;
+        partial.line_ending = bound.line_ending;
+        partial.ignore_unknown = bound.ignore_unknown;
+        partial.auto_escaped = bound.auto_escaped;
+        partial.raw = bound.raw;
+        partial.limits = bound.limits;
+        partial.validators = bound.validators;
+        partial.auto_escaped.retain(|name| partial.placeholders.contains_key(name));
+        partial.raw.retain(|name| partial.placeholders.contains_key(name));
+        partial.limits.retain(|name, _| partial.placeholders.contains_key(name));
+        partial.aliases = bound.aliases;
+        partial.aliases.retain(|alias, canonical| {
+            partial.placeholders.contains_key(alias) && partial.placeholders.contains_key(canonical)
+        });
+        partial.validators.retain(|name, _| partial.placeholders.contains_key(name));
+        Ok(partial)
+    }
+
+    /// A new template with only the named placeholder's current value baked in
+    ///
+    /// Every other placeholder is left in the content as written. Fails with
+    /// `TronError::MissingPlaceholder` if the name is unknown or unset.
+    pub fn substitute_once(&self, name: &str) -> Result<TronTemplate> {
+        if !self.is_placeholder_set(name)? {
+            return Err(TronError::MissingPlaceholder(name.to_string()));
+        }
+        let value = self.placeholders[name].clone();
+        self.with_partial(&HashMap::from([(name.to_string(), value)]))
+    }
+
+    /// Permanently replace every use of a placeholder in the content with `literal`
+    ///
+    /// The literal is inserted verbatim and escaped, so it is never scanned as
+    /// a placeholder, and the name is removed from the template along with
+    /// its value and settings. Trim markers on the replaced tags still apply.
+    pub fn replace_placeholder_with_literal(&mut self, name: &str, literal: &str) -> Result<()> {
+        if !self.placeholders.contains_key(name) {
+            return Err(TronError::MissingPlaceholder(name.to_string()));
+        }
+        let content =
+            self.baked_content(|tag| (tag.name() == name).then_some(Cow::Borrowed(literal)));
+        let mut placeholders = Self::extract_placeholders(&content, &self.delimiters, false)?;
+        for (placeholder, value) in placeholders.iter_mut() {
+            if let Some(existing) = self.placeholders.remove(placeholder) {
+                *value = existing;
+            }
+        }
+        self.content = content.into();
+        self.placeholders = placeholders;
+        self.auto_escaped.retain(|name| self.placeholders.contains_key(name));
+        self.raw.retain(|name| self.placeholders.contains_key(name));
+        self.limits.retain(|name, _| self.placeholders.contains_key(name));
+        self.aliases.retain(|alias, canonical| {
+            self.placeholders.contains_key(alias) && self.placeholders.contains_key(canonical)
+        });
+        self.validators.retain(|name, _| self.placeholders.contains_key(name));
+        self.rendered.take();
+        Ok(())
+    }
+
+    /// Rewrite every placeholder tag in the content to its canonical form
+    ///
+    /// `@[ name ]@` becomes `@[name]@` and `@[ name=default ]@` becomes
+    /// `@[name = default]@`, using the template's own delimiters. Trim markers
+    /// are kept, while comments, sections and blocks are left as written.
+    /// Rendering is unaffected; only the content text changes.
+    pub fn normalize(&mut self) {
+        let (open, close) = self.delimiters();
+        let mut content = String::with_capacity(self.content.len());
+        let mut cursor = 0;
+        for tag in scan_tags(&self.content, &self.delimiters) {
+            if tag.renders_nothing() {
+                continue;
+            }
+            content.push_str(&self.content[cursor..tag.span.start]);
+            content.push_str(open);
+            if tag.trims_before() {
+                content.push('-');
+            }
+            match tag.split_default() {
+                Some((name, default)) => content.push_str(&format!("{} = {}", name, default)),
+                None => content.push_str(tag.body()),
+            }
+            if tag.trims_after() {
+                content.push('-');
+            }
+            content.push_str(close);
+            cursor = tag.span.end;
+        }
+        content.push_str(&self.content[cursor..]);
+        self.content = content.into();
+        self.rendered.take();
+    }
+
+    /// Content with every tag for which `bake` returns text replaced by that text, escaped
+    ///
+    /// Other tags are kept exactly as written. Trim markers on replaced tags
+    /// are applied to the neighbouring literal text.
+    fn baked_content<'a, F>(&self, mut bake: F) -> String
+    where
+        F: FnMut(&Tag<'_>) -> Option<Cow<'a, str>>,
+    {
+        let mut source = SourceBuilder::new(&self.delimiters);
+        let mut cursor = 0;
+        let mut trim_next = false;
+        for tag in scan_tags(&self.content, &self.delimiters) {
+            let baked = bake(&tag);
+
+            let literal = self
+                .delimiters
+                .unescape_literal(&self.content[cursor..tag.span.start], true)
+                .concat();
+            let mut literal = literal.as_str();
+            if trim_next {
+                literal = trim_leading_whitespace(literal);
+            }
+            if baked.is_some() && tag.trims_before() {
+                literal = trim_trailing_whitespace(literal);
+            }
+            source.push_literal(literal);
+
+            match &baked {
+                Some(value) => source.push_literal(value),
+                None => source.push_tag(&self.content[tag.span.clone()]),
+            }
+            trim_next = baked.is_some() && tag.trims_after();
+            cursor = tag.span.end;
+        }
+        let rest = self.delimiters.unescape_literal(&self.content[cursor..], false).concat();
+        source.push_literal(if trim_next { trim_leading_whitespace(&rest) } else { &rest });
+        source.finish()
+    }
+
+    /// Whether every placeholder used in the content has a value or a default
+    pub fn is_complete(&self) -> bool {
+        self.required_placeholders().is_empty()
+    }
+
+    /// Names of placeholders that must still be set before the template can render
+    ///
+    /// Excludes placeholders that have a value and those whose every use
+    /// declares a default with `@[name = default]@`. Includes `__file__` and
+    /// `__template__` on a template without a path, since they cannot render
+    /// there. Sorted and without duplicates.
+    pub fn required_placeholders(&self) -> Vec<String> {
+        let mut required: Vec<String> = scan_tags(&self.content, &self.delimiters)
+            .iter()
+            .filter(|tag| tag.default_value().is_none())
+            .map(|tag| tag.name())
+            .filter(|name| match self.placeholders.get(*name) {
+                Some(value) => value.is_empty() && self.aliased_value(name).is_none(),
+                None => {
+                    MAGIC_PLACEHOLDERS.contains(name)
+                        && resolve_magic(name, self.path.as_deref()).is_none()
+                }
+            })
+            .map(str::to_string)
+            .collect();
+        required.sort();
+        required.dedup();
+        required
+    }
+
+    /// The default declared for each placeholder, omitting placeholders without one
+    ///
+    /// If a placeholder is used with several different defaults, the first one
+    /// in the content is reported. Current values are not reflected here.
+    pub fn placeholder_defaults(&self) -> HashMap<&str, &str> {
+        let mut defaults = HashMap::new();
+        for tag in scan_tags(&self.content, &self.delimiters) {
+            if let Some((name, default)) = tag.split_default() {
+                defaults.entry(name).or_insert(default);
+            }
+        }
+        defaults
+    }
+
+    /// Register a placeholder and set its value, even if it does not occur in the content
+    ///
+    /// Lets values be provided before the content that uses them exists. Values
+    /// for names that never appear in the content are harmless: `render`
+    /// simply ignores them. For names that do appear this behaves like `set`.
+    pub fn with_placeholder(&mut self, name: &str, value: &str) -> Result<()> {
+        self.placeholders.entry(name.to_string()).or_default();
+        self.set(name, value)
+    }
+
+    /// Unset every placeholder, keeping the content and all other settings
+    pub fn reset(&mut self) {
+        for value in self.placeholders.values_mut() {
+            value.clear();
+        }
+        self.limits.clear();
+        self.rendered.take();
+    }
+
+    /// Register a check that every future value for `placeholder` must pass
+    ///
+    /// Replaces any validator previously registered for the placeholder. Values
+    /// that were already set are not re-checked.
+    pub fn set_validator<F>(&mut self, placeholder: &str, validator: F) -> Result<()>
+    where
+        F: Fn(&str) -> std::result::Result<(), String> + Send + Sync + 'static,
+    {
+        if !self.placeholders.contains_key(placeholder) {
+            return Err(TronError::MissingPlaceholder(placeholder.to_string()));
+        }
+        self.validators.insert(placeholder.to_string(), Arc::new(validator));
+        Ok(())
+    }
+
+    /// Escape every `@[` in `text` as `@@[` so it renders literally instead of opening a placeholder
+    ///
+    /// `@` characters directly before an `@[` are doubled as well, so `@@[`
+    /// becomes `@@@@[`.
+    pub fn escape(text: &str) -> String {
+        Delimiters::default().escape(text)
+    }
+
+    /// Reverse `escape`, turning every `@@[` back into `@[`
+    pub fn unescape(text: &str) -> String {
+        Delimiters::default().unescape_literal(text, false).concat()
+    }
+
+    /// Escape a value for embedding inside a Rust string literal
+    ///
+    /// Backslashes, double quotes and control whitespace are replaced by their
+    /// escape sequences so the value cannot terminate or corrupt the literal.
+    pub fn escape_value(value: &str) -> String {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '"' => escaped.push_str("\\\""),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                '\0' => escaped.push_str("\\0"),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    /// Enable or disable automatic `escape_value` on a placeholder's value at render time
+    ///
+    /// Useful for placeholders that sit inside a string literal in the template,
+    /// such as `println!("@[message]@")`.
+    pub fn set_auto_escape(&mut self, placeholder: &str, enabled: bool) -> Result<()> {
+        if !self.placeholders.contains_key(placeholder) {
+            return Err(TronError::MissingPlaceholder(placeholder.to_string()));
+        }
+        if enabled {
+            self.auto_escaped.insert(placeholder.to_string());
+        } else {
+            self.auto_escaped.remove(placeholder);
+        }
+        self.rendered.take();
+        Ok(())
+    }
+
+    /// Render the template
+    ///
+    /// The output is cached until the template is next changed, so rendering
+    /// repeatedly without setting anything in between does not redo the work.
+    /// Templates using `@[__timestamp__]@` or `@[env:...]@` are never cached.
+    pub fn render(&self) -> Result<String> {
+        if let Some(rendered) = self.rendered.get() {
+            return Ok(rendered.clone());
+        }
+        let rendered = self.render_cow()?.into_owned();
+        if !self.is_volatile() {
+            let _ = self.rendered.set(rendered.clone());
+        }
+        Ok(rendered)
+    }
+
+    /// Render into a `fmt::Formatter`, for use inside `Display` implementations
+    ///
+    /// A `fmt::Error` cannot say what went wrong, so the template must be
+    /// complete: if any placeholder is still unset, nothing is written and
+    /// `fmt::Error` is returned. Use `render` to find out which values are
+    /// missing. Environment and magic placeholders that fail to resolve also
+    /// produce `fmt::Error`, possibly after part of the output was written.
+    pub fn render_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(rendered) = self.rendered.get() {
+            return f.write_str(rendered);
+        }
+        if !self.required_placeholders().is_empty() {
+            return Err(fmt::Error);
+        }
+        self.render_into(f, None, |tag, _| Err(TronError::MissingPlaceholder(tag.name().to_string())))
+            .map_err(|_| fmt::Error)
+    }
+
+    /// Render, failing as soon as the output grows beyond `max_bytes`
+    ///
+    /// The size is checked as each piece is written, so oversized output is
+    /// never fully built. Fails with `TronError::ExecutionError` when the limit
+    /// is exceeded.
+    pub fn render_bounded(&self, max_bytes: usize) -> Result<String> {
+        struct Bounded {
+            output: String,
+            max_bytes: usize,
+            exceeded: bool,
+        }
+
+        impl fmt::Write for Bounded {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                if self.output.len() + s.len() > self.max_bytes {
+                    self.exceeded = true;
+                    return Err(fmt::Error);
+                }
+                self.output.push_str(s);
+                Ok(())
+            }
+        }
+
+        let mut bounded = Bounded {
+            output: String::new(),
+            max_bytes,
+            exceeded: false,
+        };
+        let result = self.render_into(&mut bounded, None, |tag, _| {
+            Err(TronError::MissingPlaceholder(tag.name().to_string()))
+        });
+        match result {
+            Err(_) if bounded.exceeded => Err(TronError::ExecutionError(format!(
+                "Rendered output exceeds the limit of {} bytes",
+                max_bytes
+            ))),
+            Err(error) => Err(error),
+            Ok(()) => Ok(bounded.output),
+        }
+    }
+
+    /// Render, then fail if the output still contains either delimiter anywhere
+    ///
+    /// Catches values that inject delimiter text and malformed tags that were
+    /// kept as literal text, including escaped `@@[` sequences. Fails with
+    /// `TronError::InvalidSyntax` naming the first offending sequence.
+    pub fn render_strict(&self) -> Result<String> {
+        let rendered = self.render()?;
+        let (open, close) = self.delimiters();
+        let stray = [open, close]
+            .into_iter()
+            .filter_map(|delimiter| rendered.find(delimiter).map(|offset| (offset, delimiter)))
+            .min();
+        if let Some((offset, delimiter)) = stray {
+            return Err(TronError::InvalidSyntax(format!(
+                "Rendered output contains `{}` at byte {}",
+                delimiter, offset
+            )));
+        }
+        Ok(rendered)
+    }
+
+    /// Whether the output can change without the template itself changing
+    fn is_volatile(&self) -> bool {
+        scan_tags(&self.content, &self.delimiters)
+            .iter()
+            .any(|tag| tag.name() == "__timestamp__" || tag.name().starts_with(ENV_PREFIX))
+    }
+
+    /// Names of the environment variables read by `@[env:...]@` tags, sorted and without duplicates
+    pub fn env_placeholders(&self) -> Vec<String> {
+        let mut names: Vec<String> = scan_tags(&self.content, &self.delimiters)
+            .iter()
+            .filter_map(|tag| tag.name().strip_prefix(ENV_PREFIX))
+            .map(|spec| env_spec(spec).0.to_string())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Render the template, borrowing the content when there is nothing to substitute
+    ///
+    /// Static boilerplate templates without any placeholders render without
+    /// allocating.
+    pub fn render_cow(&self) -> Result<Cow<'_, str>> {
+        if !self.content.contains(&self.delimiters.open) {
+            return Ok(Cow::Borrowed(&self.content));
+        }
+        self.render_with(|tag, _| Err(TronError::MissingPlaceholder(tag.name().to_string())))
+            .map(Cow::Owned)
+    }
+
+    /// Render, leaving unset placeholders in the output exactly as written
+    pub fn render_partial(&self) -> Result<String> {
+        self.render_with(|tag, out| {
+            out.push_str(&self.content[tag.span.clone()]);
+            Ok(())
+        })
+    }
+
+    /// Render, replacing each unset placeholder with whatever `f` returns for its name
+    ///
+    /// Handy for previews that want visible markers, e.g. `|name| format!("«{}»", name)`.
+    pub fn render_partial_with<F: Fn(&str) -> String>(&self, f: F) -> Result<String> {
+        self.render_with(|tag, out| {
+            out.push_str(&f(tag.name()));
+            Ok(())
+        })
+    }
+
+    /// Render as much as possible without failing on unset placeholders
+    ///
+    /// Unset placeholders are left in the output exactly as written, and their
+    /// names are returned alongside it, sorted and without duplicates.
+    pub fn render_report(&self) -> Result<(String, Vec<String>)> {
+        let mut missing = Vec::new();
+        let rendered = self.render_with(|tag, out| {
+            out.push_str(&self.content[tag.span.clone()]);
+            missing.push(tag.name().to_string());
+            Ok(())
+        })?;
+
+        missing.sort();
+        missing.dedup();
+        Ok((rendered, missing))
+    }
+
+    /// Render, returning the output along with which set values were substituted and which went unused
+    ///
+    /// Values go unused when their placeholder does not occur in the content,
+    /// as with names registered by `with_placeholder`. Placeholders that
+    /// rendered their declared default, magic placeholders and environment
+    /// variables are not listed. Fails like `render` on missing placeholders.
+    pub fn render_with_report(&self) -> Result<(String, RenderReport)> {
+        let rendered = self.render()?;
+        let tags = scan_tags(&self.content, &self.delimiters);
+        let used: HashSet<&str> = tags
+            .iter()
+            .filter(|tag| !tag.renders_nothing())
+            .flat_map(|tag| {
+                let canonical = self.aliases.get(tag.name()).map(String::as_str);
+                std::iter::once(tag.name()).chain(canonical)
+            })
+            .collect();
+
+        let mut report = RenderReport::default();
+        for (name, value) in &self.placeholders {
+            if value.is_empty() {
+                continue;
+            }
+            if used.contains(name.as_str()) {
+                report.substituted.push(name.clone());
+            } else {
+                report.unused.push(name.clone());
+            }
+        }
+        report.substituted.sort();
+        report.unused.sort();
+        Ok((rendered, report))
+    }
+
+    /// Walk the content once, substituting every placeholder that has a value
+    /// and handing the rest to `on_missing` along with the output so far
+    fn render_with<F>(&self, on_missing: F) -> Result<String>
+    where
+        F: FnMut(&Tag<'_>, &mut String) -> Result<()>,
+    {
+        let mut result = String::with_capacity(self.content.len());
+        self.render_into(&mut result, None, on_missing)?;
+        Ok(result)
+    }
+
+    /// Like `render_with`, but writing the output into any `fmt::Write` sink
+    ///
+    /// Placeholders without a value of their own are looked up in `source`
+    /// first, then fall back to their declared default, and only then are
+    /// handed to `on_missing`.
+    fn render_into<W, F>(&self, out: &mut W, source: Option<&dyn ValueSource>, on_missing: F) -> Result<()>
+    where
+        W: fmt::Write,
+        F: FnMut(&Tag<'_>, &mut W) -> Result<()>,
+    {
+        self.render_escaped_into(out, source, EscapeMode::None, on_missing)
+    }
+
+    /// Like `render_into`, escaping every substituted value not set with `set_raw` by `mode`
+    fn render_escaped_into<W, F>(
+        &self,
+        out: &mut W,
+        source: Option<&dyn ValueSource>,
+        mode: EscapeMode,
+        mut on_missing: F,
+    ) -> Result<()>
+    where
+        W: fmt::Write,
+        F: FnMut(&Tag<'_>, &mut W) -> Result<()>,
+    {
+        let mut filled: HashMap<&str, usize> = HashMap::new();
+        for piece in split_pieces(&self.content, &self.delimiters) {
+            match piece {
+                Piece::Literal(text) => out.write_str(text).map_err(fmt_error)?,
+                Piece::Tag(tag) if self.limits.contains_key(tag.name()) => {
+                    let count = filled.entry(tag.name()).or_default();
+                    *count += 1;
+                    if *count > self.limits[tag.name()] {
+                        out.write_str(&self.content[tag.span.clone()]).map_err(fmt_error)?;
+                    } else {
+                        match self.value_for(tag.name()) {
+                            Some(value) if self.raw.contains(tag.name()) => out.write_str(&value).map_err(fmt_error)?,
+                            Some(value) => out.write_str(&mode.escape(&value)).map_err(fmt_error)?,
+                            None => on_missing(&tag, out)?,
+                        }
+                    }
+                }
+                Piece::Tag(tag) => match tag.name().strip_prefix(ENV_PREFIX) {
+                    Some(spec) => out.write_str(&mode.escape(&resolve_env(spec)?)).map_err(fmt_error)?,
+                    None => match self.value_for(tag.name()).or_else(|| {
+                        source
+                            .and_then(|source| self.source_value_for(tag.name(), source))
+                            .map(Cow::Owned)
+                            .or_else(|| self.default_for(&tag))
+                    }) {
+                        Some(value) if self.raw.contains(tag.name()) => out.write_str(&value).map_err(fmt_error)?,
+                        Some(value) => out.write_str(&mode.escape(&value)).map_err(fmt_error)?,
+                        None => on_missing(&tag, out)?,
+                    },
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Render with every substituted value escaped by `mode`, leaving the template text itself as written
+    ///
+    /// With `EscapeMode::Html`, values, defaults and environment variables are
+    /// made safe to embed in markup. Values set with `set_raw` are inserted
+    /// unchanged. Fails like `render` on missing placeholders.
+    pub fn render_escaped(&self, mode: EscapeMode) -> Result<String> {
+        if mode == EscapeMode::None {
+            return self.render();
+        }
+        let mut result = String::with_capacity(self.content.len());
+        self.render_escaped_into(&mut result, None, mode, |tag, _| {
+            Err(TronError::MissingPlaceholder(tag.name().to_string()))
+        })?;
+        Ok(result)
+    }
+
+    /// Render straight into a `fmt::Write` sink such as a `String`
+    ///
+    /// Avoids building an intermediate `String` when accumulating output with
+    /// `write!`. If rendering fails part way, the output written so far stays
+    /// in `w`.
+    pub fn render_to_fmt<W: fmt::Write>(&self, w: &mut W) -> Result<()> {
+        if let Some(rendered) = self.rendered.get() {
+            return w.write_str(rendered).map_err(fmt_error);
+        }
+        self.render_into(w, None, |tag, _| Err(TronError::MissingPlaceholder(tag.name().to_string())))
+    }
+
+    /// The text a placeholder renders as, or `None` if it has no value yet
+    fn value_for(&self, name: &str) -> Option<Cow<'_, str>> {
+        if MAGIC_PLACEHOLDERS.contains(&name) {
+            return resolve_magic(name, self.path.as_deref()).map(Cow::Owned);
+        }
+
+        let value = self
+            .placeholders
+            .get(name)
+            .filter(|value| !value.is_empty())
+            .or_else(|| self.aliased_value(name))?;
+        Some(format_value(value, self.auto_escaped.contains(name), self.line_ending))
+    }
+
+    /// The value of the canonical placeholder `name` is an alias of, if it has one
+    fn aliased_value(&self, name: &str) -> Option<&String> {
+        let canonical = self.aliases.get(name)?;
+        self.placeholders.get(canonical).filter(|value| !value.is_empty())
+    }
+
+    /// The text a placeholder renders as when its value comes from `source`
+    fn source_value_for(&self, name: &str, source: &(impl ValueSource + ?Sized)) -> Option<String> {
+        let value = source.get(name).filter(|value| !value.is_empty())?;
+        Some(format_value(&value, self.auto_escaped.contains(name), self.line_ending).into_owned())
+    }
+
+    /// Render, looking up every placeholder without a value of its own in `source`
+    ///
+    /// Values set on the template take precedence. Values from the source are
+    /// escaped and normalized like set values. A placeholder the source has no
+    /// value for uses its declared default, or fails with
+    /// `TronError::MissingPlaceholder` if it has none.
+    pub fn render_with_source(&self, source: &impl ValueSource) -> Result<String> {
+        let mut result = String::with_capacity(self.content.len());
+        self.render_into(&mut result, Some(source), |tag, _| {
+            Err(TronError::MissingPlaceholder(tag.name().to_string()))
+        })?;
+        Ok(result)
+    }
+
+    /// Render, asking `resolver` for every placeholder without a value of its own
+    ///
+    /// The resolver is only queried for placeholders that are used and not
+    /// `set`, and behaves like a source passed to `render_with_source`.
+    pub fn render_with_resolver(&self, resolver: &dyn PlaceholderResolver) -> Result<String> {
+        self.render_with_source(&ResolverSource(resolver))
+    }
+
+    /// The text a tag renders as from its declared default, if it has one
+    fn default_for(&self, tag: &Tag<'_>) -> Option<Cow<'_, str>> {
+        let default = tag.default_value()?;
+        Some(Cow::Owned(
+            format_value(default, self.auto_escaped.contains(tag.name()), self.line_ending).into_owned(),
+        ))
+    }
+
+    /// Append template source equivalent to this template with its current values baked in
+    ///
+    /// Literal text and substituted values stay literal, while unset
+    /// placeholders are written back as `@[name]@` tags, or `@[name = default]@`
+    /// when they declare a default, using the default delimiters `source` is
+    /// built with. Placeholders unset locally take their value from `shared`
+    /// when it has one. Occurrences past a `set_limited` limit become their
+    /// tag text, as in `render`. Comments and trim markers are already applied.
+    fn push_resolved_source(
+        &self,
+        shared: &HashMap<String, String>,
+        source: &mut SourceBuilder<'_>,
+    ) {
+        let mut filled: HashMap<&str, usize> = HashMap::new();
+        for piece in split_pieces(&self.content, &self.delimiters) {
+            match piece {
+                Piece::Literal(text) => source.push_literal(text),
+                Piece::Tag(tag)
+                    if self.limits.get(tag.name()).is_some_and(|&limit| {
+                        let count = filled.entry(tag.name()).or_default();
+                        *count += 1;
+                        *count > limit
+                    }) =>
+                {
+                    source.push_literal(&self.content[tag.span.clone()])
+                }
+                Piece::Tag(tag) => match self.value_for(tag.name()).or_else(|| {
+                    match tag.name().strip_prefix(ENV_PREFIX) {
+                        Some(spec) => resolve_env(spec).ok().map(Cow::Owned),
+                        None => self.source_value_for(tag.name(), shared).map(Cow::Owned),
+                    }
+                }) {
+                    Some(value) => source.push_literal(&value),
+                    None => source.push_tag(&match tag.default_value() {
+                        Some(default) => format!(
+                            "{}{} = {}{}",
+                            OPEN_DELIMITER,
+                            tag.name(),
+                            default,
+                            CLOSE_DELIMITER
+                        ),
+                        None => format!("{}{}{}", OPEN_DELIMITER, tag.name(), CLOSE_DELIMITER),
+                    }),
+                },
+            }
+        }
+    }
+
+    /// Parse the template once into a form that renders quickly with different values
+    ///
+    /// The compiled form captures the current content, path, line ending and
+    /// auto-escaping settings, but not any values that have been set.
+    pub fn compile(&self) -> CompiledTemplate {
+        let segments = split_pieces(&self.content, &self.delimiters)
+            .into_iter()
+            .map(|piece| match piece {
+                Piece::Literal(text) => Segment::Literal(text.to_string()),
+                Piece::Tag(tag) => match tag.name().strip_prefix(ENV_PREFIX) {
+                    Some(spec) => Segment::Env(spec.to_string()),
+                    None => Segment::Placeholder {
+                        name: tag.name().to_string(),
+                        default: tag.default_value().map(str::to_string),
+                    },
+                },
+            })
+            .collect();
+
+        CompiledTemplate {
+            segments,
+            path: self.path.clone(),
+            line_ending: self.line_ending,
+            auto_escaped: self.auto_escaped.clone(),
+        }
+    }
+
+    /// Byte ranges of every occurrence of the named placeholder, delimiters included
+    ///
+    /// Returns an empty list if the name does not occur in the content.
+    pub fn placeholder_uses(&self, name: &str) -> Vec<Range<usize>> {
+        scan_tags(&self.content, &self.delimiters)
+            .into_iter()
+            .filter(|tag| tag.name() == name)
+            .map(|tag| tag.span)
+            .collect()
+    }
+
+    /// Every placeholder occurrence as its name and one-based line, in content order
+    ///
+    /// A name used several times on one line is listed once for that line.
+    /// Comments, sections and blocks are not placeholders and are skipped.
+    pub fn placeholder_lines(&self) -> Vec<(String, usize)> {
+        let mut lines: Vec<(String, usize)> = Vec::new();
+        let mut line = 1;
+        let mut cursor = 0;
+        for tag in scan_tags(&self.content, &self.delimiters) {
+            line += self.content[cursor..tag.span.start].matches('\n').count();
+            cursor = tag.span.start;
+            if tag.renders_nothing() {
+                continue;
+            }
+            let seen = lines
+                .iter()
+                .rev()
+                .take_while(|(_, l)| *l == line)
+                .any(|(name, _)| name == tag.name());
+            if !seen {
+                lines.push((tag.name().to_string(), line));
+            }
+        }
+        lines
+    }
+
+    /// Fail unless the named placeholder occurs exactly once in the content
+    ///
+    /// Returns `TronError::MissingPlaceholder` if it does not occur at all and
+    /// `TronError::InvalidSyntax` with the number of occurrences if it repeats.
+    pub fn assert_unique(&self, name: &str) -> Result<()> {
+        match self.placeholder_uses(name).len() {
+            0 => Err(TronError::MissingPlaceholder(name.to_string())),
+            1 => Ok(()),
+            count => Err(TronError::InvalidSyntax(format!(
+                "Placeholder {} must be unique but occurs {} times",
+                name, count
+            ))),
+        }
+    }
+
+    /// Split the raw content around the only occurrence of the named placeholder
+    ///
+    /// Returns the text before and after the placeholder tag, or `None` if the
+    /// placeholder does not occur exactly once.
+    pub fn split_on_placeholder(&self, name: &str) -> Option<(String, String)> {
+        match self.placeholder_uses(name).as_slice() {
+            [span] => Some((
+                self.content[..span.start].to_string(),
+                self.content[span.end..].to_string(),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Find delimiter sequences that did not parse as part of a placeholder
+    ///
+    /// A stray `@[` without a matching `]@` (or the reverse) is silently kept as
+    /// literal text by `render`, which usually means a typo in the template.
+    /// Each entry describes one such sequence and its byte offset.
+    pub fn suspicious_sequences(&self) -> Vec<String> {
+        self.stray_delimiters()
+            .into_iter()
+            .map(|(offset, problem, delimiter)| {
+                format!("{} `{}` at byte {}", problem, delimiter, offset)
+            })
+            .collect()
+    }
+
+    /// Check a template's structure without creating or rendering it
+    ///
+    /// Fails with `TronError::InvalidSyntax` describing the first unclosed or
+    /// unopened delimiter, unbalanced section marker or, in a template that
+    /// extends another, unclosed block, with its line and column, both
+    /// starting at 1, or with `TronError::ParseAt` for a malformed
+    /// placeholder. Include tags are accepted without being expanded. Meant
+    /// as a lint over template files, e.g. in CI.
+    pub fn validate_syntax(content: &str) -> Result<()> {
+        Self::parse(content, Delimiters::default(), true)?.check_syntax()
+    }
+
+    /// The structural checks of `validate_syntax`, run against this template's own delimiters
+    fn check_syntax(&self) -> Result<()> {
+        let content = &*self.content;
+        if let Some(&(offset, problem, delimiter)) = self.stray_delimiters().first() {
+            let (line, column) = line_column(content, offset);
+            return Err(TronError::InvalidSyntax(format!(
+                "{} `{}` at line {}, column {}",
+                problem, delimiter, line, column
+            )));
+        }
+        let tags = scan_tags(content, &self.delimiters);
+        let unclosed_block = tags
+            .iter()
+            .any(|tag| tag.extends_target().is_some())
+            .then(|| unclosed_block(content, &self.delimiters))
+            .flatten()
+            .map(|offset| (offset, "block is never closed"));
+        if let Some((offset, problem)) = self.section_spans().1.or(unclosed_block) {
+            let (line, column) = line_column(content, offset);
+            return Err(TronError::InvalidSyntax(format!(
+                "{} at line {}, column {}",
+                problem, line, column
+            )));
+        }
+        Ok(())
+    }
+
+    /// Names of every `@[section:name]@ ... @[endsection]@` region, in order of appearance
+    ///
+    /// Sections may be nested. Section markers are removed when rendering, so
+    /// they only label parts of the content. Unclosed sections are not listed.
+    pub fn sections(&self) -> Vec<String> {
+        self.section_spans()
+            .0
+            .into_iter()
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+
+    /// Raw content between the markers of the named section, if it is declared
+    ///
+    /// If several sections share the name, the first one is returned.
+    pub fn section(&self, name: &str) -> Option<&str> {
+        self.section_spans()
+            .0
+            .into_iter()
+            .find(|(section, _)| *section == name)
+            .map(|(_, span)| &self.content[span])
+    }
+
+    /// Every closed section with the byte range of its content, ordered by
+    /// start, plus the offset and description of the first unbalanced marker
+    fn section_spans(&self) -> (Vec<SectionSpan<'_>>, Option<(usize, &'static str)>) {
+        region_spans(&self.content, &self.delimiters, Region::Section)
+    }
+
+    /// Byte offset, problem and text of every delimiter left outside a tag, in order
+    fn stray_delimiters(&self) -> Vec<(usize, &'static str, &str)> {
+        let mut suspicious = Vec::new();
+        let mut literal_start = 0;
+
+        let tags = scan_tags(&self.content, &self.delimiters);
+        let spans = tags
+            .iter()
+            .map(|tag| tag.span.clone())
+            .chain(std::iter::once(self.content.len()..self.content.len()));
+
+        for span in spans {
+            let literal = &self.content[literal_start..span.start];
+            let (open, close) = self.delimiters();
+
+            // An escaped opening delimiter pairs with the next closing one, so
+            // escaped placeholder syntax such as `@@[name]@` is not reported
+            let opens = std::iter::successors(self.delimiters.find_open(literal, 0), |&offset| {
+                self.delimiters.find_open(literal, offset + open.len())
+            });
+            let mut delimiters: Vec<(usize, bool)> = opens
+                .map(|offset| (offset, true))
+                .chain(literal.match_indices(close).map(|(offset, _)| (offset, false)))
+                .collect();
+            delimiters.sort();
+
+            let mut escaped_open = false;
+            for (offset, is_open) in delimiters {
+                if !is_open {
+                    if !std::mem::take(&mut escaped_open) {
+                        suspicious.push((literal_start + offset, "unopened", close));
+                    }
+                } else if self.delimiters.escape_run(&literal[..offset]) % 2 == 1 {
+                    escaped_open = true;
+                } else {
+                    suspicious.push((literal_start + offset, "unclosed", open));
+                }
+            }
+            literal_start = span.end;
+        }
+
+        suspicious.sort();
+        suspicious
+    }
+
+    /// Length of the raw content in bytes
+    pub fn byte_len(&self) -> usize {
+        self.content.len()
+    }
+
+    /// Total bytes taken up by tags, delimiters included, all of which disappear at render
+    ///
+    /// `byte_len() - placeholder_byte_overhead()` is the size of the literal
+    /// text, a lower bound on the rendered size before values are added.
+    pub fn placeholder_byte_overhead(&self) -> usize {
+        scan_tags(&self.content, &self.delimiters)
+            .iter()
+            .map(|tag| tag.span.len())
+            .sum()
+    }
+
+    /// Stable hash of the template source and delimiters, ignoring any values that have been set
+    ///
+    /// Uses 64-bit FNV-1a so the result is identical across runs, platforms and
+    /// compiler versions, making it suitable as an on-disk cache key.
+    pub fn content_hash(&self) -> u64 {
+        // 0xff never occurs in UTF-8, so it cleanly separates the parts
+        let mut bytes = self.content.as_bytes().to_vec();
+        for delimiter in [&self.delimiters.open, &self.delimiters.close] {
+            bytes.push(0xff);
+            bytes.extend_from_slice(delimiter.as_bytes());
+        }
+        if self.delimiters.escape == EscapeStyle::Backslash {
+            bytes.extend_from_slice(&[0xff, b'\\']);
+        }
+        fnv1a(&bytes)
+    }
+
+    /// One line per placeholder, sorted by name, with long values shortened for logging
+    ///
+    /// Line breaks in values are collapsed into single spaces and anything
+    /// beyond `max_len` characters is cut off with `…`. This is for
+    /// diagnostics only; use `render` for real output.
+    pub fn preview(&self, max_len: usize) -> String {
+        let mut names: Vec<&String> = self.placeholders.keys().collect();
+        names.sort();
+
+        let lines: Vec<String> = names
+            .into_iter()
+            .map(|name| {
+                let value = &self.placeholders[name];
+                if value.is_empty() {
+                    return format!("{} = <unset>", name);
+                }
+                let collapsed = value.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+                match collapsed.char_indices().nth(max_len) {
+                    Some((cut, _)) => format!("{} = {:?}…", name, &collapsed[..cut]),
+                    None => format!("{} = {:?}", name, collapsed),
+                }
+            })
+            .collect();
+        lines.join("\n")
+    }
+}
+
+impl fmt::Debug for TronTemplate {
+    /// Lists placeholders sorted by name so the output is deterministic,
+    /// marking each one as either `<unset>` or its current value.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.placeholders.keys().collect();
+        names.sort();
+
+        let placeholders: Vec<PlaceholderState<'_>> = names
+            .into_iter()
+            .map(|name| PlaceholderState {
+                name,
+                value: &self.placeholders[name],
+            })
+            .collect();
+
+        let mut debug = f.debug_struct("TronTemplate");
+        debug.field("content", &self.content);
+        if let Some(path) = &self.path {
+            debug.field("path", path);
+        }
+        if self.delimiters() != (OPEN_DELIMITER, CLOSE_DELIMITER) {
+            debug.field("delimiters", &self.delimiters());
+        }
+        if self.delimiters.escape != EscapeStyle::Double {
+            debug.field("escape_style", &self.delimiters.escape);
+        }
+        debug.field("placeholders", &placeholders).finish()
+    }
+}
+
+/// Debug view of a single placeholder, rendered as `name = <unset>` or `name = "value"`
+struct PlaceholderState<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+impl fmt::Debug for PlaceholderState<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.value.is_empty() {
+            write!(f, "{} = <unset>", self.name)
+        } else {
+            write!(f, "{} = {:?}", self.name, self.value)
+        }
+    }
+}
+
+const OPEN_DELIMITER: &str = "@[";
+const CLOSE_DELIMITER: &str = "]@";
+
+/// How an opening delimiter is escaped so it stays literal text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EscapeStyle {
+    /// Repeat the first character of the delimiter, as in `@@[`
+    #[default]
+    Double,
+    /// Put a backslash before the delimiter, as in `\@[`
+    Backslash,
+}
+
+/// The pair of strings that surround a placeholder
+#[derive(Debug, Clone)]
+struct Delimiters {
+    open: String,
+    close: String,
+    escape: EscapeStyle,
+    /// Matches an opening delimiter up to the nearest closing one after it
+    pattern: Regex,
+}
+
+/// Pattern matching an `open` delimiter, the text after it and the nearest `close` delimiter
+fn tag_pattern(open: &str, close: &str) -> Regex {
+    Regex::new(&format!("(?s){}(.*?){}", regex::escape(open), regex::escape(close)))
+        .expect("escaped delimiters always form a valid pattern")
+}
+
+impl Delimiters {
+    /// Where the next opening delimiter in `text` at or after `from` starts
+    ///
+    /// When the delimiter starts with the escape character, as `@[` does in
+    /// the double style, a run like `@@@[` holds the delimiter at its end and
+    /// the characters before it are its escape run.
+    fn find_open(&self, text: &str, from: usize) -> Option<usize> {
+        let mut start = from + text[from..].find(self.open.as_str())?;
+        if let Some(c) = self.escape_char() {
+            while text[start..].starts_with(c)
+                && text[start + c.len_utf8()..].starts_with(self.open.as_str())
+            {
+                start += c.len_utf8();
+            }
+        }
+        Some(start)
+    }
+
+    /// How many escape characters `preceding` ends with
+    ///
+    /// A run of escape characters before an opening delimiter stands for
+    /// half as many literal ones, and an odd run also escapes the delimiter:
+    /// `@@[` is a literal `@[` while `@@@[name]@` is an `@` followed by a placeholder.
+    fn escape_run(&self, preceding: &str) -> usize {
+        self.escape_char()
+            .map_or(0, |c| preceding.chars().rev().take_while(|&x| x == c).count())
+    }
+
+    fn escape_char(&self) -> Option<char> {
+        match self.escape {
+            EscapeStyle::Double => self.open.chars().next(),
+            EscapeStyle::Backslash => Some('\\'),
+        }
+    }
+
+    /// Escape every opening delimiter in `text` so it stays literal
+    fn escape(&self, text: &str) -> String {
+        self.escape_literal(text, false)
+    }
+
+    /// Escape `text` so it reads back unchanged, `before_tag` when a tag directly follows it
+    ///
+    /// Escape characters in front of an opening delimiter are doubled and
+    /// one more is added, and a run at the very end is doubled when a tag
+    /// follows so it cannot escape that tag.
+    fn escape_literal(&self, text: &str, before_tag: bool) -> String {
+        let Some(c) = self.escape_char() else {
+            return text.to_string();
+        };
+        let mut escaped = String::with_capacity(text.len());
+        let mut literal_start = 0;
+        while let Some(start) = self.find_open(text, literal_start) {
+            let run = self.escape_run(&text[literal_start..start]);
+            escaped.push_str(&text[literal_start..start]);
+            escaped.extend(std::iter::repeat_n(c, run + 1));
+            escaped.push_str(&self.open);
+            literal_start = start + self.open.len();
+        }
+        let rest = &text[literal_start..];
+        escaped.push_str(rest);
+        if before_tag {
+            escaped.extend(std::iter::repeat_n(c, self.escape_run(rest)));
+        }
+        escaped
+    }
+
+    /// Reverse `escape_literal` for literal text, `before_tag` when a tag directly follows it
+    fn unescape_literal<'a>(&self, literal: &'a str, before_tag: bool) -> Vec<&'a str> {
+        let Some(c) = self.escape_char() else {
+            return vec![literal];
+        };
+        let width = c.len_utf8();
+        let mut parts = Vec::new();
+        let mut cursor = 0;
+        let mut literal_start = 0;
+        while let Some(start) = self.find_open(literal, literal_start) {
+            let run = self.escape_run(&literal[literal_start..start]);
+            parts.push(&literal[cursor..start - (run - run / 2) * width]);
+            cursor = start;
+            literal_start = start + self.open.len();
+        }
+        let run = if before_tag {
+            self.escape_run(&literal[literal_start..])
+        } else {
+            0
+        };
+        parts.push(&literal[cursor..literal.len() - run / 2 * width]);
+        parts
+    }
+
+    fn new(open: &str, close: &str) -> Result<Self> {
+        if open.is_empty() || close.is_empty() {
+            return Err(TronError::InvalidSyntax(
+                "Placeholder delimiters must not be empty".into(),
+            ));
+        }
+        Ok(Self {
+            open: open.to_string(),
+            close: close.to_string(),
+            escape: EscapeStyle::Double,
+            pattern: tag_pattern(open, close),
+        })
+    }
+}
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        static PATTERN: OnceLock<Regex> = OnceLock::new();
+        Self {
+            open: OPEN_DELIMITER.to_string(),
+            close: CLOSE_DELIMITER.to_string(),
+            escape: EscapeStyle::Double,
+            pattern: PATTERN
+                .get_or_init(|| tag_pattern(OPEN_DELIMITER, CLOSE_DELIMITER))
+                .clone(),
+        }
+    }
+}
+
+/// A single `@[...]@` tag found in template content
+struct Tag<'a> {
+    /// Byte range of the whole tag, delimiters included
+    span: Range<usize>,
+    /// Raw text between the delimiters
+    inner: &'a str,
+    /// Whether the tag opens a block that is closed later, or closes an open block or section
+    paired: bool,
+}
+
+impl<'a> Tag<'a> {
+    /// Placeholder name with trim markers, default and surrounding whitespace removed
+    fn name(&self) -> &'a str {
+        match self.split_default() {
+            Some((name, _)) => name,
+            None => self.body(),
+        }
+    }
+
+    /// The default declared with `@[name = default]@`, if any
+    fn default_value(&self) -> Option<&'a str> {
+        self.split_default().map(|(_, default)| default)
+    }
+
+    /// Name and default of a `@[name = default]@` tag, both trimmed
+    ///
+    /// `@[env:...]@` tags are never split, so their own defaults may contain `=`.
+    fn split_default(&self) -> Option<(&'a str, &'a str)> {
+        let body = self.body();
+        if body.starts_with(ENV_PREFIX) {
+            return None;
+        }
+        body.split_once('=')
+            .map(|(name, default)| (name.trim_end(), default.trim_start()))
+    }
+
+    /// Text between the delimiters with trim markers and surrounding whitespace removed
+    fn body(&self) -> &'a str {
+        let mut body = self.inner;
+        if self.trims_before() {
+            body = &body[1..];
+        }
+        if self.trims_after() {
+            body = &body[..body.len() - 1];
+        }
+        body.trim()
+    }
+
+    /// Whether the tag is a `@[# ...]@` comment, which renders as nothing
+    fn is_comment(&self) -> bool {
+        self.inner.starts_with('#')
+    }
+
+    /// The section name if this tag opens a `@[section:name]@` region
+    fn section_start(&self) -> Option<&'a str> {
+        self.body().strip_prefix(SECTION_PREFIX).map(str::trim)
+    }
+
+    /// Whether the tag is the `@[endsection]@` closing an open section
+    fn is_section_end(&self) -> bool {
+        self.paired && self.body() == END_SECTION
+    }
+
+    /// The block name if this tag opens a `@[block name]@` region that is closed later
+    fn block_start(&self) -> Option<&'a str> {
+        self.body()
+            .strip_prefix(BLOCK_PREFIX)
+            .filter(|_| self.paired)
+            .map(str::trim)
+    }
+
+    /// Whether the tag is the `@[end]@` closing an open block
+    fn is_block_end(&self) -> bool {
+        self.paired && self.body() == END_BLOCK
+    }
+
+    /// Whether the tag reads `@[block name]@` but no `@[end]@` closes it
+    fn is_unclosed_block(&self) -> bool {
+        !self.paired && self.body().starts_with(BLOCK_PREFIX)
+    }
+
+    /// The path named by an `@[extends path]@` tag
+    fn extends_target(&self) -> Option<&'a str> {
+        self.body().strip_prefix(EXTENDS_DIRECTIVE).map(str::trim)
+    }
+
+    /// Whether the tag is markup that is removed from the output rather than a placeholder
+    fn renders_nothing(&self) -> bool {
+        self.is_comment()
+            || self.section_start().is_some()
+            || self.is_section_end()
+            || self.block_start().is_some()
+            || self.is_block_end()
+    }
+
+    /// Whether the tag opens with a `-` trim marker, as in `@[- name]@`
+    fn trims_before(&self) -> bool {
+        self.inner.starts_with('-')
+    }
+
+    /// Whether the tag closes with a `-` trim marker, as in `@[name -]@`
+    fn trims_after(&self) -> bool {
+        self.inner.len() > 1 && self.inner.ends_with('-')
+    }
+}
+
+/// A piece of template content: literal text with trim markers applied, or a tag
+enum Piece<'a> {
+    Literal(&'a str),
+    Tag(Tag<'a>),
+}
+
+/// Split `content` into literal text and tags, in order
+fn split_pieces<'a>(content: &'a str, delimiters: &Delimiters) -> Vec<Piece<'a>> {
+    let mut pieces = Vec::new();
+    let mut cursor = 0;
+    let mut trim_next = false;
+
+    for tag in scan_tags(content, delimiters) {
+        let mut literal = &content[cursor..tag.span.start];
+        if trim_next {
+            literal = trim_leading_whitespace(literal);
+        }
+        let mut before_tag = true;
+        if tag.trims_before() {
+            let trimmed = trim_trailing_whitespace(literal);
+            before_tag = trimmed.len() == literal.len();
+            literal = trimmed;
+        }
+        push_literal(&mut pieces, literal, delimiters, before_tag);
+
+        cursor = tag.span.end;
+        trim_next = tag.trims_after();
+        if !tag.renders_nothing() {
+            pieces.push(Piece::Tag(tag));
+        }
+    }
+
+    let literal = &content[cursor..];
+    push_literal(
+        &mut pieces,
+        if trim_next { trim_leading_whitespace(literal) } else { literal },
+        delimiters,
+        false,
+    );
+    pieces
+}
+
+/// Add literal text to `pieces` with its escape runs decoded, `before_tag` when a tag directly follows it
+fn push_literal<'a>(
+    pieces: &mut Vec<Piece<'a>>,
+    literal: &'a str,
+    delimiters: &Delimiters,
+    before_tag: bool,
+) {
+    pieces.extend(
+        delimiters
+            .unescape_literal(literal, before_tag)
+            .into_iter()
+            .map(Piece::Literal),
+    );
+}
+
+/// Template source assembled from literal text and tags kept as written
+///
+/// Literal text is buffered and escaped as a whole once the next tag or the
+/// end is reached, so escape characters at the edge of one piece can never
+/// combine with a delimiter from the next.
+struct SourceBuilder<'d> {
+    delimiters: &'d Delimiters,
+    source: String,
+    literal: String,
+}
+
+impl<'d> SourceBuilder<'d> {
+    fn new(delimiters: &'d Delimiters) -> Self {
+        Self {
+            delimiters,
+            source: String::new(),
+            literal: String::new(),
+        }
+    }
+
+    /// Append text that should render exactly as given
+    fn push_literal(&mut self, text: &str) {
+        self.literal.push_str(text);
+    }
+
+    /// Append template source, keeping its tags as written
+    fn push_source(&mut self, source: &str) {
+        let delimiters = self.delimiters;
+        let mut cursor = 0;
+        for tag in scan_tags(source, delimiters) {
+            self.push_literal(
+                &delimiters.unescape_literal(&source[cursor..tag.span.start], true).concat(),
+            );
+            self.push_tag(&source[tag.span.clone()]);
+            cursor = tag.span.end;
+        }
+        self.push_literal(&delimiters.unescape_literal(&source[cursor..], false).concat());
+    }
+
+    /// Append the raw source of a tag
+    fn push_tag(&mut self, tag: &str) {
+        let literal = std::mem::take(&mut self.literal);
+        self.source.push_str(&self.delimiters.escape_literal(&literal, true));
+        self.source.push_str(tag);
+    }
+
+    fn finish(mut self) -> String {
+        self.source.push_str(&self.delimiters.escape_literal(&self.literal, false));
+        self.source
+    }
+}
+
+/// Strip the spaces and tabs at the end of `text`, then at most one line break
+fn trim_trailing_whitespace(text: &str) -> &str {
+    let text = text.trim_end_matches([' ', '\t']);
+    text.strip_suffix("\r\n")
+        .or_else(|| text.strip_suffix('\n'))
+        .unwrap_or(text)
+}
+
+/// Strip the spaces and tabs at the start of `text`, then at most one line break
+fn trim_leading_whitespace(text: &str) -> &str {
+    let text = text.trim_start_matches([' ', '\t']);
+    text.strip_prefix("\r\n")
+        .or_else(|| text.strip_prefix('\n'))
+        .unwrap_or(text)
+}
+
+/// Locate every placeholder tag in `content`, in order
+///
+/// Candidates are matched by the delimiters' pattern, from an opening
+/// delimiter to the nearest closing one. When an opening delimiter is followed by another one before any closing
+/// delimiter, the innermost opening wins and the earlier one is left as
+/// literal text. Empty tags (`@[]@`) and escaped openings (`@@[`) are not
+/// placeholders.
+fn scan_tags<'a>(content: &'a str, delimiters: &Delimiters) -> Vec<Tag<'a>> {
+    let Delimiters { open, close, .. } = delimiters;
+    let mut tags = Vec::new();
+    let mut cursor = 0;
+    let mut literal_start = 0;
+
+    while let Some(candidate) = delimiters.pattern.captures_at(content, cursor) {
+        let (Some(whole), Some(text)) = (candidate.get(0), candidate.get(1)) else {
+            break;
+        };
+        let start = delimiters.find_open(content, whole.start()).unwrap_or(whole.start());
+        if delimiters.escape_run(&content[literal_start..start]) % 2 == 1 {
+            cursor = start + open.len();
+            continue;
+        }
+        let inner_start = start + open.len();
+        if inner_start > text.end() {
+            cursor = inner_start;
+            continue;
+        }
+        let inner_end = text.end();
+        let inner = &content[inner_start..inner_end];
+
+        if let Some(reopen) = inner.rfind(open.as_str()) {
+            cursor = inner_start + reopen;
+            continue;
+        }
+        if inner.is_empty() {
+            cursor = inner_start;
+            continue;
+        }
+
+        let end = inner_end + close.len();
+        tags.push(Tag {
+            span: start..end,
+            inner,
+            paired: false,
+        });
+        cursor = end;
+        literal_start = end;
+    }
+
+    // Closing tags only count while their kind of region is open, and blocks
+    // only once closed, so `@[end]@` anywhere else stays an ordinary placeholder
+    let mut open_sections = 0;
+    let mut open_blocks = Vec::new();
+    for index in 0..tags.len() {
+        let body = tags[index].body();
+        if body.starts_with(SECTION_PREFIX) {
+            open_sections += 1;
+        } else if body == END_SECTION && open_sections > 0 {
+            open_sections -= 1;
+            tags[index].paired = true;
+        } else if body.starts_with(BLOCK_PREFIX) {
+            open_blocks.push(index);
+        } else if body == END_BLOCK {
+            if let Some(start) = open_blocks.pop() {
+                tags[start].paired = true;
+                tags[index].paired = true;
+            }
+        }
+    }
+
+    tags
+}
+
+/// 64-bit FNV-1a hash, stable across runs, platforms and compiler versions
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Prefix of a tag that splices another template file into the content
+const INCLUDE_DIRECTIVE: &str = "include:";
+
+/// Prefix of a tag that splices another file in verbatim, without parsing it
+const RAW_INCLUDE_DIRECTIVE: &str = "raw_include:";
+
+/// Read `path` and recursively splice in every `@[include:...]@` it contains
+///
+/// Includes resolve under `base_dir` when given, otherwise relative to the
+/// including file. A file containing `@[extends path]@` is replaced by the
+/// named base file with the file's own blocks overriding the base's. `stack` holds the files currently being expanded so cycles
+/// are reported instead of recursing forever. `@[raw_include:...]@` files are
+/// spliced in with their opening delimiters escaped, so they render exactly as
+/// written and contribute no placeholders.
+fn expand_includes(
+    path: &Path,
+    base_dir: Option<&Path>,
+    delimiters: &Delimiters,
+    stack: &mut Vec<PathBuf>,
+) -> Result<String> {
+    let canonical = fs::canonicalize(path)?;
+    if stack.contains(&canonical) {
+        return Err(TronError::InvalidSyntax(format!(
+            "Include cycle detected at {}",
+            path.display()
+        )));
+    }
+
+    let content = fs::read_to_string(path)?;
+    let dir = base_dir
+        .or_else(|| path.parent())
+        .unwrap_or_else(|| Path::new(""));
+
+    stack.push(canonical);
+    let mut source = SourceBuilder::new(delimiters);
+    let mut cursor = 0;
+    for tag in scan_tags(&content, delimiters) {
+        source.push_literal(
+            &delimiters.unescape_literal(&content[cursor..tag.span.start], true).concat(),
+        );
+        if let Some(target) = tag.name().strip_prefix(RAW_INCLUDE_DIRECTIVE) {
+            source.push_literal(&fs::read_to_string(dir.join(target.trim()))?);
+        } else if let Some(target) = tag.name().strip_prefix(INCLUDE_DIRECTIVE) {
+            source.push_source(&expand_includes(
+                &dir.join(target.trim()),
+                base_dir,
+                delimiters,
+                stack,
+            )?);
+        } else {
+            source.push_tag(&content[tag.span.clone()]);
+        }
+        cursor = tag.span.end;
+    }
+    source.push_literal(&delimiters.unescape_literal(&content[cursor..], false).concat());
+    let mut expanded = source.finish();
+
+    let parent = scan_tags(&expanded, delimiters)
+        .iter()
+        .find_map(|tag| tag.extends_target())
+        .map(|target| dir.join(target));
+    if let Some(parent) = parent {
+        let base = expand_includes(&parent, base_dir, delimiters, stack)?;
+        expanded = apply_block_overrides(&base, &expanded, delimiters)?;
+    }
+    stack.pop();
+
+    Ok(expanded)
+}
+
+/// A template pre-parsed into literal text and placeholder slots
+///
+/// Produced by `TronTemplate::compile` for hot loops that render the same
+/// template many times; rendering only stitches the pieces together.
+#[derive(Debug, Clone)]
+pub struct CompiledTemplate {
+    segments: Vec<Segment>,
+    path: Option<PathBuf>,
+    line_ending: LineEnding,
+    auto_escaped: HashSet<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Placeholder { name: String, default: Option<String> },
+    /// An `@[env:...]@` tag, holding the text after the prefix
+    Env(String),
+}
+
+impl CompiledTemplate {
+    /// Render using the given placeholder values
+    pub fn render(&self, values: &HashMap<String, String>) -> Result<String> {
+        let mut result = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => result.push_str(text),
+                Segment::Env(spec) => result.push_str(&resolve_env(spec)?),
+                Segment::Placeholder { name, .. } if MAGIC_PLACEHOLDERS.contains(&name.as_str()) => {
+                    let value = resolve_magic(name, self.path.as_deref())
+                        .ok_or_else(|| TronError::MissingPlaceholder(name.clone()))?;
+                    result.push_str(&value);
+                }
+                Segment::Placeholder { name, default } => {
+                    let value = values
+                        .get(name)
+                        .filter(|value| !value.is_empty())
+                        .or(default.as_ref())
+                        .ok_or_else(|| TronError::MissingPlaceholder(name.clone()))?;
+                    result.push_str(&format_value(
+                        value,
+                        self.auto_escaped.contains(name),
+                        self.line_ending,
+                    ));
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Resolve one of the reserved `MAGIC_PLACEHOLDERS`, or `None` if it has no value
+fn resolve_magic(name: &str, path: Option<&Path>) -> Option<String> {
+    match name {
+        "__file__" => path.map(|path| path.display().to_string()),
+        "__template__" => path
+            .and_then(Path::file_name)
+            .map(|file_name| file_name.to_string_lossy().into_owned()),
+        "__timestamp__" => Some(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default()
+                .to_string(),
+        ),
+        "__version__" => Some(env!("CARGO_PKG_VERSION").to_string()),
+        _ => None,
+    }
+}
+
+/// Split the text of an `@[env:...]@` tag into the variable name and optional default
+fn env_spec(spec: &str) -> (&str, Option<&str>) {
+    match spec.split_once(':') {
+        Some((name, default)) => (name.trim(), Some(default)),
+        None => (spec.trim(), None),
+    }
+}
+
+/// Read the variable named by an `@[env:...]@` tag, falling back to its default
+fn resolve_env(spec: &str) -> Result<String> {
+    let (name, default) = env_spec(spec);
+    match (std::env::var(name), default) {
+        (Ok(value), _) => Ok(value),
+        (Err(_), Some(default)) => Ok(default.to_string()),
+        (Err(_), None) => Err(TronError::MissingEnvVar(name.to_string())),
+    }
+}
+
+/// A `TronError::ParseAt` pointing at a byte offset into `content`
+fn parse_error_at(content: &str, offset: usize, message: &str) -> TronError {
+    let (line, column) = line_column(content, offset);
+    let line_start = content[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[offset..].find('\n').map_or(content.len(), |i| offset + i);
+    TronError::ParseAt {
+        message: message.to_string(),
+        line,
+        column,
+        line_text: content[line_start..line_end].trim_end_matches('\r').to_string(),
+    }
+}
+
+/// One-based line and column, counted in characters, of a byte offset into `text`
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+/// Prefix every non-blank line of `text` except the first with `indent`
+fn indent_lines(text: &str, indent: &str) -> String {
+    let mut indented = String::with_capacity(text.len());
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        if i > 0 && !line.trim().is_empty() {
+            indented.push_str(indent);
+        }
+        indented.push_str(line);
+    }
+    indented
+}
+
+/// Report a failed write to a `fmt::Write` sink
+fn fmt_error(_: fmt::Error) -> TronError {
+    TronError::Io(std::io::Error::other("formatter error"))
+}
+
+/// Prepare a placeholder value for insertion into rendered output
+fn format_value(value: &str, auto_escape: bool, line_ending: LineEnding) -> Cow<'_, str> {
+    if auto_escape {
+        Cow::Owned(TronTemplate::escape_value(value))
+    } else {
+        line_ending.normalize(value)
+    }
+}
+
+/// Collects parsing options for a `TronTemplate` before creating it
+///
+/// `TronTemplate::new` and `TronTemplate::from_file` are shortcuts for a
+/// builder with every option left at its default, e.g.
+/// `TronTemplateBuilder::new().build_from_str(source)`. Options are changed
+/// by chaining, as in
+/// `TronTemplateBuilder::new().delimiters("{{", "}}").strict(true).build_from_str(source)`.
+#[derive(Debug, Clone)]
+pub struct TronTemplateBuilder {
+    delimiters: Option<(String, String)>,
+    escape_style: EscapeStyle,
+    strict: bool,
+    allow_comments: bool,
+}
+
+impl Default for TronTemplateBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TronTemplateBuilder {
+    pub fn new() -> Self {
+        Self {
+            delimiters: None,
+            escape_style: EscapeStyle::default(),
+            strict: false,
+            allow_comments: true,
+        }
+    }
+
+    /// Use custom placeholder delimiters instead of `@[` and `]@`
+    ///
+    /// Invalid delimiters are reported when building.
+    pub fn delimiters(mut self, open: &str, close: &str) -> Self {
+        self.delimiters = Some((open.to_string(), close.to_string()));
+        self
+    }
+
+    /// Choose how opening delimiters are escaped, `EscapeStyle::Double` by default
+    pub fn escape_style(mut self, style: EscapeStyle) -> Self {
+        self.escape_style = style;
+        self
+    }
+
+    /// Reject content that `TronTemplate::validate_syntax` would reject
+    ///
+    /// Stray delimiters, unbalanced sections and, in a template that extends
+    /// another, unclosed blocks then fail the build with
+    /// `TronError::InvalidSyntax` instead of being kept as literal text.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Whether `@[# ...]@` comments are accepted; when disallowed they fail the build
+    pub fn allow_comments(mut self, allow: bool) -> Self {
+        self.allow_comments = allow;
+        self
+    }
+
+    /// Create a template from a string with these options
+    pub fn build_from_str(&self, content: &str) -> Result<TronTemplate> {
+        self.check(TronTemplate::from_parts(content, self.parsed_delimiters()?)?)
+    }
+
+    /// Load a template from a file with these options, expanding includes like `TronTemplate::from_file`
+    pub fn build_from_file<P: AsRef<Path>>(&self, path: P) -> Result<TronTemplate> {
+        self.check(TronTemplate::load(path.as_ref(), None, self.parsed_delimiters()?)?)
+    }
+
+    fn parsed_delimiters(&self) -> Result<Delimiters> {
+        let mut delimiters = match &self.delimiters {
+            Some((open, close)) => Delimiters::new(open, close)?,
+            None => Delimiters::default(),
+        };
+        delimiters.escape = self.escape_style;
+        Ok(delimiters)
+    }
+
+    /// Apply the options that restrict which templates are accepted
+    fn check(&self, template: TronTemplate) -> Result<TronTemplate> {
+        if !self.allow_comments {
+            let tags = scan_tags(&template.content, &template.delimiters);
+            if let Some(comment) = tags.iter().find(|tag| tag.is_comment()) {
+                return Err(parse_error_at(&template.content, comment.span.start, "comments are not allowed"));
+            }
+        }
+        if self.strict {
+            template.check_syntax()?;
+        }
+        Ok(template)
+    }
+}
+
+/// Parses each distinct template source once and hands out cheap copies of it
+///
+/// Templates from the pool share their content, so generators that use the
+/// same snippet many times keep a single copy of its text in memory and skip
+/// re-parsing it. Each returned template has its own values.
+#[derive(Debug, Default)]
+pub struct TemplatePool {
+    templates: HashMap<Arc<str>, TronTemplate>,
+}
+
+impl TemplatePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A fresh template for `source`, parsed only the first time this source is seen
+    pub fn get(&mut self, source: &str) -> Result<TronTemplate> {
+        if let Some(template) = self.templates.get(source) {
+            return Ok(template.clone());
+        }
+        let template = TronTemplate::new(source)?;
+        self.templates.insert(template.content.clone(), template.clone());
+        Ok(template)
+    }
+
+    /// Number of distinct sources in the pool
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+}
+
+/// Assemble multiple templates together
+#[derive(Debug)]
+pub struct TronAssembler {
+    templates: Vec<TronRef>,
+    dedup_output: bool,
+    /// Values every member falls back to, when sharing values is enabled
+    shared: Option<HashMap<String, String>>,
+}
+
+impl Default for TronAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TronAssembler {
+    pub fn new() -> Self {
+        Self {
+            templates: Vec::new(),
+            dedup_output: false,
+            shared: None,
+        }
+    }
+
+    /// Keep global values in one store shared by every template instead of copying them into each
+    ///
+    /// While enabled, `set_global` and `set_ref_global` only update the shared
+    /// store, and each template renders against it: values set on a template
+    /// itself override the shared ones, and placeholders it leaves unset take
+    /// the shared value. Shared values are not checked by template validators.
+    /// Disabling discards the shared store.
+    pub fn share_values(&mut self, enabled: bool) {
+        match (enabled, self.shared.is_some()) {
+            (true, false) => self.shared = Some(HashMap::new()),
+            (false, true) => self.shared = None,
+            _ => {}
+        }
+    }
+
+    /// Skip any rendered template identical to one already emitted
+    ///
+    /// Applies to `render_all`, `render_all_to_writer` and `render_each`. The
+    /// comparison is on the final rendered text and the first occurrence wins.
+    pub fn dedup_output(&mut self, enabled: bool) {
+        self.dedup_output = enabled;
+    }
+
+    /// Add a template reference to the assembler
+    pub fn add_template(&mut self, template: TronRef) {
+        self.templates.push(template);
+    }
+
+    /// Keep only the templates for which `f` returns `true`, preserving their order
+    ///
+    /// For example `assembler.retain(|t| t.inner().is_complete())` drops every
+    /// template that still has unset placeholders.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&TronRef) -> bool,
+    {
+        self.templates.retain(f);
+    }
+
+    /// The first template, in insertion order, for which `f` returns `true`
+    ///
+    /// For example `assembler.find(|t| !t.inner().placeholder_uses("title").is_empty())`
+    /// finds the template that uses `title`.
+    pub fn find<F>(&self, mut f: F) -> Option<&TronRef>
+    where
+        F: FnMut(&TronRef) -> bool,
+    {
+        self.templates.iter().find(|template| f(template))
+    }
+
+    /// Like `find`, but returning the template mutably so it can be changed in place
+    pub fn find_mut<F>(&mut self, mut f: F) -> Option<&mut TronRef>
+    where
+        F: FnMut(&TronRef) -> bool,
+    {
+        self.templates.iter_mut().find(|template| f(template))
+    }
+
+    /// Remove every template, along with any shared values
+    pub fn clear(&mut self) {
+        self.templates.clear();
+        if let Some(shared) = &mut self.shared {
+            shared.clear();
+        }
+    }
+
+    /// Unset every placeholder in every template and the shared store, keeping the templates
+    pub fn reset_all(&mut self) {
+        for template in &mut self.templates {
+            template.inner_mut().reset();
+        }
+        if let Some(shared) = &mut self.shared {
+            shared.clear();
+        }
+    }
+
+    /// Reorder the templates with a comparator; the sort is stable
+    pub fn sort_by<F>(&mut self, compare: F)
+    where
+        F: FnMut(&TronRef, &TronRef) -> std::cmp::Ordering,
+    {
+        self.templates.sort_by(compare);
+    }
+
+    /// Reorder the templates by name; unnamed templates come first
+    pub fn sort_by_name(&mut self) {
+        self.sort_by(|a, b| a.name().cmp(&b.name()));
+    }
+
+    /// Set a value for a placeholder across all templates
+    pub fn set_global(&mut self, placeholder: &str, value: &str) -> Result<()> {
+        if let Some(shared) = &mut self.shared {
+            shared.insert(placeholder.to_string(), value.to_string());
+            return Ok(());
+        }
+        for template in &mut self.templates {
             if template.inner().placeholders.contains_key(placeholder) {
                 template.set(placeholder, value)?;
             }
@@ -206,73 +3795,2155 @@ impl TronAssembler {
         Ok(())
     }
 
-    /// Set a template reference as a value for a placeholder across all templates
-    pub fn set_ref_global(&mut self, placeholder: &str, template_ref: TronRef) -> Result<()> {
-        for template in &mut self.templates {
-            if template.inner().placeholders.contains_key(placeholder) {
-                template.set_ref(placeholder, template_ref.clone())?;
+    /// Set a template reference as a value for a placeholder across all templates
+    pub fn set_ref_global(&mut self, placeholder: &str, template_ref: TronRef) -> Result<()> {
+        if self.shared.is_some() {
+            let rendered = template_ref.render()?;
+            for template in &mut self.templates {
+                if template.inner().placeholders.contains_key(placeholder) {
+                    for dependency in template_ref.active_dependencies() {
+                        template.add_dependency(dependency);
+                    }
+                }
+            }
+            return self.set_global(placeholder, &rendered);
+        }
+        for template in &mut self.templates {
+            if template.inner().placeholders.contains_key(placeholder) {
+                template.set_ref(placeholder, template_ref.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Render all templates and combine them
+    ///
+    /// A `@[member:name]@` placeholder in any template is replaced with the
+    /// rendered output of the member named `name`, which is still emitted in
+    /// its own place as well. Members referencing each other in a cycle fail
+    /// with `TronError::CyclicComposition`.
+    pub fn render_all(&self) -> Result<String> {
+        let mut result = String::new();
+        let mut seen = HashSet::new();
+        let mut rendered = vec![None; self.templates.len()];
+        for index in 0..self.templates.len() {
+            let rendered = self.render_member(index, &mut rendered, &mut Vec::new())?;
+            if self.is_new_output(&mut seen, &rendered) {
+                result.push_str(&rendered);
+                result.push('\n');
+            }
+        }
+        Ok(result)
+    }
+
+    /// Render every template that can be rendered, collecting the failures instead of stopping
+    ///
+    /// The output is what `render_all` would produce with the failed templates
+    /// left out. Each failure is returned with the index of its template, in
+    /// order, so a whole batch of broken templates can be reported at once.
+    pub fn try_render_all(&self) -> (String, Vec<(usize, TronError)>) {
+        let mut result = String::new();
+        let mut errors = Vec::new();
+        let mut seen = HashSet::new();
+        let mut rendered = vec![None; self.templates.len()];
+        for index in 0..self.templates.len() {
+            match self.render_member(index, &mut rendered, &mut Vec::new()) {
+                Ok(output) => {
+                    if self.is_new_output(&mut seen, &output) {
+                        result.push_str(&output);
+                        result.push('\n');
+                    }
+                }
+                Err(error) => errors.push((index, error)),
+            }
+        }
+        (result, errors)
+    }
+
+    /// Render the member at `index`, against the shared store when there is one
+    ///
+    /// Members it references through `@[member:name]@` are rendered first and
+    /// memoized in `rendered`; `visiting` holds the chain of members currently
+    /// being rendered, to detect cycles.
+    fn render_member(
+        &self,
+        index: usize,
+        rendered: &mut [Option<String>],
+        visiting: &mut Vec<usize>,
+    ) -> Result<String> {
+        if let Some(output) = &rendered[index] {
+            return Ok(output.clone());
+        }
+        if visiting.contains(&index) {
+            let start = visiting.iter().position(|&i| i == index).unwrap_or_default();
+            let cycle = visiting[start..]
+                .iter()
+                .chain(Some(&index))
+                .map(|&i| self.templates[i].name().unwrap_or("<unnamed>").to_string())
+                .collect();
+            return Err(TronError::CyclicComposition(cycle));
+        }
+
+        visiting.push(index);
+        let template = self.templates[index].inner();
+        let mut members = HashMap::new();
+        for (placeholder, value) in &template.placeholders {
+            let Some(member) = placeholder.strip_prefix(MEMBER_PREFIX) else {
+                continue;
+            };
+            let position = self.templates.iter().position(|t| t.name() == Some(member));
+            if let (true, Some(position)) = (value.is_empty(), position) {
+                let output = self.render_member(position, rendered, visiting)?;
+                members.insert(placeholder.clone(), output);
+            }
+        }
+        visiting.pop();
+
+        let output = match (&self.shared, members.is_empty()) {
+            (None, true) => self.templates[index].render()?,
+            (shared, _) => {
+                let source = |name: &str| {
+                    members
+                        .get(name)
+                        .or_else(|| shared.as_ref().and_then(|shared| shared.get(name)))
+                        .cloned()
+                };
+                self.templates[index].render_with_fallbacks(Some(&source))?
+            }
+        };
+        rendered[index] = Some(output.clone());
+        Ok(output)
+    }
+
+    /// Whether `rendered` should be emitted, remembering it when deduplicating
+    fn is_new_output(&self, seen: &mut HashSet<String>, rendered: &str) -> bool {
+        !self.dedup_output || seen.insert(rendered.to_string())
+    }
+
+    /// Render every template straight into `writer`, following each with `separator`
+    ///
+    /// Equivalent to writing `render_all` with a `"\n"` separator, but without
+    /// building the combined output in memory first.
+    pub fn render_all_to_writer<W: std::io::Write>(&self, writer: &mut W, separator: &str) -> Result<()> {
+        let mut seen = HashSet::new();
+        let mut rendered = vec![None; self.templates.len()];
+        for index in 0..self.templates.len() {
+            let rendered = self.render_member(index, &mut rendered, &mut Vec::new())?;
+            if self.is_new_output(&mut seen, &rendered) {
+                writer.write_all(rendered.as_bytes())?;
+                writer.write_all(separator.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flatten the assembly into a single template for further composition
+    ///
+    /// Each member contributes its content with its current values baked in,
+    /// joined by `separator`. Placeholders that are still unset are preserved,
+    /// so the returned template exposes the union of every member's remaining
+    /// placeholders. Dependencies are not carried over, since a `TronTemplate`
+    /// has none; wrap the result in a `TronRef` to add them again.
+    pub fn into_template(self, separator: &str) -> Result<TronTemplate> {
+        let shared = self.shared.unwrap_or_default();
+        let delimiters = Delimiters::default();
+        let mut source = SourceBuilder::new(&delimiters);
+        for (index, template) in self.templates.iter().enumerate() {
+            if index > 0 {
+                source.push_literal(separator);
+            }
+            template.inner().push_resolved_source(&shared, &mut source);
+        }
+        TronTemplate::new(&source.finish())
+    }
+
+    /// Render each template separately, in insertion order
+    ///
+    /// Stops at the first template that fails to render, reporting its
+    /// position as `TronError::AtIndex`.
+    pub fn render_each(&self) -> Result<Vec<String>> {
+        let mut outputs = Vec::with_capacity(self.templates.len());
+        let mut seen = HashSet::new();
+        let mut rendered = vec![None; self.templates.len()];
+        for index in 0..self.templates.len() {
+            let rendered = self.render_member(index, &mut rendered, &mut Vec::new()).map_err(|e| TronError::AtIndex {
+                index,
+                source: Box::new(e),
+            })?;
+            if self.is_new_output(&mut seen, &rendered) {
+                outputs.push(rendered);
+            }
+        }
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_composition() -> Result<()> {
+        // Create a function template
+        let function = TronTemplate::new("fn @[name]@() {\n    @[body]@\n}")?;
+        let mut function_ref = TronRef::new(function);
+        
+        // Create a print template to insert into the function
+        let print = TronTemplate::new("println!(\"@[message]@\");")?;
+        let mut print_ref = TronRef::new(print);
+        print_ref.set("message", "Hello from Tron!")?;
+        
+        // Compose the templates
+        function_ref.set("name", "greet")?;
+        function_ref.set_ref("body", print_ref)?;
+        
+        let rendered = function_ref.render()?;
+        assert!(rendered.contains("fn greet()"));
+        assert!(rendered.contains("println!(\"Hello from Tron!\");"));
+        
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_composition() -> Result<()> {
+        let outer = TronTemplate::new("mod test {\n    @[function]@\n}")?;
+        let mut outer_ref = TronRef::new(outer);
+        
+        let inner = TronTemplate::new("fn helper() {\n    @[body]@\n}")?;
+        let mut inner_ref = TronRef::new(inner);
+        
+        let print = TronTemplate::new("println!(\"@[message]@\");")?;
+        let mut print_ref = TronRef::new(print);
+        print_ref.set("message", "Nested template")?;
+        
+        inner_ref.set_ref("body", print_ref)?;
+        outer_ref.set_ref("function", inner_ref)?;
+        
+        let rendered = outer_ref.render()?;
+        assert!(rendered.contains("mod test {"));
+        assert!(rendered.contains("fn helper()"));
+        assert!(rendered.contains("println!(\"Nested template\");"));
+        
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug_output_is_sorted_and_marks_unset() -> Result<()> {
+        let mut template = TronTemplate::new("@[zeta]@ @[alpha]@ @[mid]@")?;
+        template.set("mid", "value")?;
+
+        assert_eq!(
+            format!("{:?}", template),
+            "TronTemplate { content: \"@[zeta]@ @[alpha]@ @[mid]@\", placeholders: \
+             [alpha = <unset>, mid = \"value\", zeta = <unset>] }"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_each_keeps_templates_separate() -> Result<()> {
+        let mut first = TronRef::new(TronTemplate::new("struct @[name]@;")?);
+        first.set("name", "First")?;
+        let second = TronRef::new(TronTemplate::new("struct Second;")?);
+
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(first);
+        assembler.add_template(second);
+
+        assert_eq!(
+            assembler.render_each()?,
+            vec!["struct First;".to_string(), "struct Second;".to_string()]
+        );
+
+        assembler.add_template(TronRef::new(TronTemplate::new("@[unset]@")?));
+        match assembler.render_each() {
+            Err(TronError::AtIndex { index, source }) => {
+                assert_eq!(index, 2);
+                assert!(matches!(*source, TronError::MissingPlaceholder(ref name) if name == "unset"));
+            }
+            other => panic!("expected an indexed error, got {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_magic_placeholders() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("magic.tron");
+        fs::write(&file, "// @[__template__]@ v@[__version__]@\nfn @[name]@() {}")?;
+
+        let mut template = TronTemplate::from_file(&file)?;
+        assert!(!template.placeholders.contains_key("__template__"));
+        template.set("name", "generated")?;
+
+        assert_eq!(
+            template.render()?,
+            format!("// magic.tron v{}\nfn generated() {{}}", env!("CARGO_PKG_VERSION"))
+        );
+
+        let in_memory = TronTemplate::new("// @[__file__]@")?;
+        assert!(!in_memory.is_complete());
+        assert_eq!(in_memory.required_placeholders(), vec!["__file__"]);
+        assert!(in_memory.clone().with_path("main.tron").is_complete());
+        assert!(matches!(
+            in_memory.render(),
+            Err(TronError::MissingPlaceholder(name)) if name == "__file__"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_hash_ignores_values() -> Result<()> {
+        let first = TronTemplate::new("fn @[name]@() {}")?;
+        let mut second = TronTemplate::new("fn @[name]@() {}")?;
+        second.set("name", "main")?;
+
+        assert_eq!(first.content_hash(), second.content_hash());
+        assert_ne!(
+            first.content_hash(),
+            TronTemplate::new("fn @[other]@() {}")?.content_hash()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_suspicious_sequences() -> Result<()> {
+        let clean = TronTemplate::new("fn @[name]@() {}")?;
+        assert!(clean.suspicious_sequences().is_empty());
+
+        let broken = TronTemplate::new("fn @[name() {} @[ok]@ ]@")?;
+        assert_eq!(broken.placeholders.len(), 1);
+        assert!(broken.placeholders.contains_key("ok"));
+        assert_eq!(
+            broken.suspicious_sequences(),
+            vec!["unclosed `@[` at byte 3", "unopened `]@` at byte 22"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_ending_normalization() -> Result<()> {
+        let source = "fn main() {\r\n    @[body]@\r\n}\r\n";
+
+        let mut kept = TronTemplate::new(source)?;
+        kept.set("body", "a();\nb();")?;
+        assert_eq!(kept.render()?, "fn main() {\r\n    a();\nb();\r\n}\r\n");
+
+        let mut lf = TronTemplate::new(source)?.with_line_ending(LineEnding::Lf);
+        lf.set("body", "a();\r\nb();")?;
+        assert_eq!(lf.render()?, "fn main() {\n    a();\nb();\n}\n");
+
+        let mut crlf = TronTemplate::new(source)?.with_line_ending(LineEnding::CrLf);
+        crlf.set("body", "a();\nb();")?;
+        assert_eq!(crlf.render()?, "fn main() {\r\n    a();\r\nb();\r\n}\r\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_dependencies() -> Result<()> {
+        let template_ref = TronRef::new(TronTemplate::new("fn main() {}")?)
+            .with_dependency("serde = \"1.0\"")
+            .with_dependencies(vec!["a = \"1\"", "b = \"2\""]);
+
+        assert_eq!(
+            template_ref.dependencies,
+            vec!["serde = \"1.0\"", "a = \"1\"", "b = \"2\""]
+        );
+
+        let extra: &[&str] = &["serde = \"1.0\"", "c = \"3\"", "c = \"3\""];
+        let deduped = template_ref.with_dependencies(extra);
+        assert_eq!(
+            deduped.dependencies,
+            vec!["serde = \"1.0\"", "a = \"1\"", "b = \"2\"", "c = \"3\""]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_escaped_values() -> Result<()> {
+        assert_eq!(
+            TronTemplate::escape_value("say \"hi\"\\n"),
+            "say \\\"hi\\\"\\\\n"
+        );
+
+        let mut template = TronTemplate::new("println!(\"@[message]@\");")?;
+        template.set_auto_escape("message", true)?;
+        template.set("message", "She said \"hello\"\n")?;
+        assert_eq!(template.render()?, "println!(\"She said \\\"hello\\\"\\n\");");
+
+        assert!(template.set_auto_escape("unknown", true).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_report_leaves_unset_placeholders() -> Result<()> {
+        let mut template = TronTemplate::new("fn @[name]@(@[args]@) -> @[ret]@ { @[args]@ }")?;
+        template.set("name", "draft")?;
+
+        let (rendered, missing) = template.render_report()?;
+        assert_eq!(rendered, "fn draft(@[args]@) -> @[ret]@ { @[args]@ }");
+        assert_eq!(missing, vec!["args", "ret"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_cow_borrows_static_content() -> Result<()> {
+        let boilerplate = TronTemplate::new("use std::fmt;")?;
+        assert!(matches!(boilerplate.render_cow()?, Cow::Borrowed("use std::fmt;")));
+
+        let mut dynamic = TronTemplate::new("use @[path]@;")?;
+        dynamic.set("path", "std::io")?;
+        assert!(matches!(dynamic.render_cow()?, Cow::Owned(ref s) if s == "use std::io;"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cargo_manifest_from_dependencies() -> Result<()> {
+        let template_ref = TronRef::new(TronTemplate::new("fn main() {}")?)
+            .with_dependencies(["serde = \"1.0\"", "anyhow = \"1\""]);
+
+        assert_eq!(
+            template_ref.to_cargo_manifest("generated")?,
+            "[package]\nname = \"generated\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+             [dependencies]\nserde = \"1.0\"\nanyhow = \"1\"\n"
+        );
+
+        let conflicting = template_ref.with_dependency("serde = \"0.9\"");
+        assert!(matches!(
+            conflicting.to_cargo_manifest("generated"),
+            Err(TronError::Parse(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trim_markers() -> Result<()> {
+        let mut template = TronTemplate::new("fn main() {\n    @[- body -]@\n}\nlet x = [@[items -]@  \n];")?;
+        assert!(template.placeholders.contains_key("body"));
+        assert!(template.placeholders.contains_key("items"));
+
+        template.set("body", " run();")?;
+        template.set("items", "1, 2")?;
+        assert_eq!(template.render()?, "fn main() { run();}\nlet x = [1, 2];");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_io_error_accessor() {
+        let error = TronTemplate::from_file("does/not/exist.tron").unwrap_err();
+        assert_eq!(
+            error.io_error().map(std::io::Error::kind),
+            Some(std::io::ErrorKind::NotFound)
+        );
+        assert!(TronError::Parse("bad".into()).io_error().is_none());
+    }
+
+    #[cfg(feature = "execute")]
+    #[test]
+    fn test_execution_script_header() -> Result<()> {
+        let template_ref = TronRef::new(TronTemplate::new("fn main() {}")?)
+            .with_dependencies(["serde = \"1.0\"", "anyhow = \"1\""]);
+
+        assert_eq!(
+            template_ref.execution().with_edition("2021").script()?,
+            "//! ```cargo\n//! [package]\n//! edition = \"2021\"\n//! [dependencies]\n\
+             //! serde = \"1.0\"\n//! anyhow = \"1\"\n//! ```\nfn main() {}"
+        );
+        assert_eq!(TronRef::new(TronTemplate::new("fn main() {}")?).execution().script()?, "fn main() {}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compiled_template_renders_many_times() -> Result<()> {
+        let template = TronTemplate::new("let @[- name]@ = @[value]@;")?;
+        let compiled = template.compile();
+
+        for i in 0..3 {
+            let values = HashMap::from([
+                ("name".to_string(), format!("x{}", i)),
+                ("value".to_string(), i.to_string()),
+            ]);
+            assert_eq!(compiled.render(&values)?, format!("letx{} = {};", i, i));
+        }
+
+        assert!(matches!(
+            compiled.render(&HashMap::new()),
+            Err(TronError::MissingPlaceholder(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_failed_truncates_long_scripts() {
+        let short = TronError::ScriptFailed {
+            message: "boom".into(),
+            script: "fn main() {}".into(),
+        };
+        assert_eq!(
+            short.to_string(),
+            "Execution error: boom\n--- generated script ---\nfn main() {}"
+        );
+
+        let script = "x".repeat(SCRIPT_EXCERPT_LIMIT + 10);
+        let long = TronError::ScriptFailed {
+            message: "boom".into(),
+            script: script.clone(),
+        };
+        assert!(long.to_string().ends_with("... (10 more bytes truncated)"));
+        assert!(matches!(long, TronError::ScriptFailed { script: full, .. } if full == script));
+    }
+
+    #[test]
+    fn test_includes_resolve_under_base_dir() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let base = root.path().join("templates");
+        fs::create_dir_all(base.join("common"))?;
+        fs::create_dir_all(root.path().join("src"))?;
+
+        fs::write(base.join("common/header.tron"), "// @[title]@\n@[include:common/license.tron]@")?;
+        fs::write(base.join("common/license.tron"), "// MIT")?;
+        let main = root.path().join("src/main.tron");
+        fs::write(&main, "@[include:common/header.tron]@\nfn main() {}")?;
+
+        let mut template = TronTemplate::from_file_with_base(&main, &base)?;
+        template.set("title", "Generated")?;
+        assert_eq!(template.render()?, "// Generated\n// MIT\nfn main() {}");
+
+        assert!(TronTemplate::from_file(&main).is_err());
+
+        let cyclic = base.join("cyclic.tron");
+        fs::write(&cyclic, "@[include:cyclic.tron]@")?;
+        assert!(matches!(
+            TronTemplate::from_file(&cyclic),
+            Err(TronError::InvalidSyntax(_))
+        ));
+
+        assert!(TronTemplate::new("@[include:common/license.tron]@").is_err());
+        assert!(TronTemplate::new("@[raw_include:common/license.tron]@").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_partial_with_custom_markers() -> Result<()> {
+        let mut template = TronTemplate::new("fn @[name]@() -> @[ ret ]@ {}")?;
+        template.set("name", "draft")?;
+
+        assert_eq!(template.render_partial()?, "fn draft() -> @[ ret ]@ {}");
+        assert_eq!(
+            template.render_partial_with(|name| format!("«{}»", name))?,
+            "fn draft() -> «ret» {}"
+        );
+        assert_eq!(template.render_partial_with(|_| String::new())?, "fn draft() ->  {}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_placeholder_uses() -> Result<()> {
+        let template = TronTemplate::new("@[a]@ + @[ b ]@ = @[a]@")?;
+
+        let uses = template.placeholder_uses("a");
+        assert_eq!(uses, vec![0..5, 18..23]);
+        assert!(uses.iter().all(|span| &template.content[span.clone()] == "@[a]@"));
+        assert_eq!(template.placeholder_uses("b"), vec![8..15]);
+        assert!(template.placeholder_uses("c").is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_all_to_writer_matches_render_all() -> Result<()> {
+        let mut assembler = TronAssembler::new();
+        let mut header = TronRef::new(TronTemplate::new("// @[title]@")?);
+        header.set("title", "Generated")?;
+        assembler.add_template(header);
+        assembler.add_template(TronRef::new(TronTemplate::new("fn main() {}")?));
+
+        let mut output = Vec::new();
+        assembler.render_all_to_writer(&mut output, "\n")?;
+        assert_eq!(String::from_utf8(output).unwrap(), assembler.render_all()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validator_rejects_invalid_identifier() -> Result<()> {
+        let mut template = TronTemplate::new("fn @[name]@() {}")?;
+        template.set_validator("name", |value| {
+            let mut chars = value.chars();
+            let valid = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+                && chars.all(|c| c.is_alphanumeric() || c == '_');
+            if valid {
+                Ok(())
+            } else {
+                Err(format!("`{}` is not a valid identifier", value))
+            }
+        })?;
+
+        assert!(matches!(
+            template.set("name", "2fast"),
+            Err(TronError::InvalidValue { placeholder, .. }) if placeholder == "name"
+        ));
+        template.set("name", "fast")?;
+        assert_eq!(template.render()?, "fn fast() {}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_comments_are_stripped() -> Result<()> {
+        let mut template = TronTemplate::new("@[a]@@[# a note about [brackets] and names ]@@[b]@")?;
+        assert_eq!(template.placeholders.len(), 2);
+
+        template.set("a", "x")?;
+        template.set("b", "y")?;
+        assert_eq!(template.render()?, "xy");
+        assert!(template.suspicious_sequences().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_templates_before_rendering() -> Result<()> {
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(TronRef::new(TronTemplate::new("impl Foo {}")?).with_name("3-impls"));
+        assembler.add_template(TronRef::new(TronTemplate::new("use std::fmt;")?).with_name("1-imports"));
+        assembler.add_template(TronRef::new(TronTemplate::new("struct Foo;")?).with_name("2-types"));
+
+        assembler.sort_by_name();
+        assert_eq!(assembler.render_all()?, "use std::fmt;\nstruct Foo;\nimpl Foo {}\n");
+
+        assembler.sort_by(|a, b| b.name().cmp(&a.name()));
+        assert_eq!(assembler.render_each()?[0], "impl Foo {}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_template_keeps_dependencies() -> Result<()> {
+        let template_ref = TronRef::new(TronTemplate::new("FN @[NAME]@() {}")?)
+            .with_dependency("serde = \"1.0\"");
+
+        let mapped = template_ref.map_template(|template| {
+            TronTemplate::new(&template.content.to_lowercase())
+        })?;
+
+        assert!(mapped.inner().placeholders.contains_key("name"));
+        assert_eq!(mapped.dependencies, vec!["serde = \"1.0\""]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_compiler_diagnostics() {
+        let stderr = "\
+warning: unused variable: `y`
+ --> /tmp/script.rs:2:9
+  |
+error[E0425]: cannot find value `x` in this scope
+ --> /tmp/script.rs:3:5
+  |
+3 |     x
+  |     ^ not found in this scope
+
+error: aborting due to 1 previous error; 1 warning emitted
+";
+        let diagnostics = TronError::ExecutionError(stderr.into()).diagnostics();
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic {
+                    level: DiagnosticLevel::Warning,
+                    code: None,
+                    message: "unused variable: `y`".into(),
+                    file: Some("/tmp/script.rs".into()),
+                    line: Some(2),
+                    column: Some(9),
+                },
+                Diagnostic {
+                    level: DiagnosticLevel::Error,
+                    code: Some("E0425".into()),
+                    message: "cannot find value `x` in this scope".into(),
+                    file: Some("/tmp/script.rs".into()),
+                    line: Some(3),
+                    column: Some(5),
+                },
+            ]
+        );
+        assert!(TronError::Parse("bad".into()).diagnostics().is_empty());
+    }
+
+    #[cfg(feature = "execute")]
+    #[test]
+    fn test_cached_script_path_is_stable() {
+        let first = ExecuteOptions::cached_script_path("fn main() {}");
+        assert_eq!(first, ExecuteOptions::cached_script_path("fn main() {}"));
+        assert_ne!(first, ExecuteOptions::cached_script_path("fn main() { run(); }"));
+        assert_eq!(first.extension().and_then(|ext| ext.to_str()), Some("rs"));
+    }
+
+    #[test]
+    fn test_custom_delimiters_survive_clone_and_composition() -> Result<()> {
+        let child = TronTemplate::with_delimiters("println!(\"{{ message }}\");", "{{", "}}")?;
+        let mut child_ref = TronRef::new(child.clone());
+        child_ref.set("message", "@[not a placeholder]@")?;
+        assert_eq!(child.delimiters(), ("{{", "}}"));
+
+        let mut parent = TronRef::new(TronTemplate::new("fn main() { @[body]@ }")?);
+        parent.set_ref("body", child_ref)?;
+        assert_eq!(
+            parent.render()?,
+            "fn main() { println!(\"@[not a placeholder]@\"); }"
+        );
+
+        assert_ne!(
+            child.content_hash(),
+            TronTemplate::with_delimiters(&child.content, "<<", ">>")?.content_hash()
+        );
+        assert!(TronTemplate::with_delimiters("x", "", "}}").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_on_placeholder() -> Result<()> {
+        let template = TronTemplate::new("mod @[name]@ {\n@[ marker ]@\n}")?;
+        assert_eq!(
+            template.split_on_placeholder("marker"),
+            Some(("mod @[name]@ {\n".to_string(), "\n}".to_string()))
+        );
+        assert_eq!(template.split_on_placeholder("missing"), None);
+        assert_eq!(TronTemplate::new("@[x]@ @[x]@")?.split_on_placeholder("x"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_escaped_delimiters_round_trip() -> Result<()> {
+        let literal = "let s = \"@[not_a_placeholder]@\"; let t = \"@@[\";";
+        let escaped = TronTemplate::escape(literal);
+        assert_eq!(TronTemplate::unescape(&escaped), literal);
+
+        let mut template = TronTemplate::new(&format!("{} @[name]@", escaped))?;
+        assert_eq!(template.placeholders.len(), 1);
+        assert!(template.suspicious_sequences().is_empty());
+        template.set("name", "x")?;
+        assert_eq!(template.render()?, format!("{} x", literal));
+
+        let adjacent = TronTemplate::new("@[a]@@[b]@")?;
+        assert_eq!(adjacent.placeholders.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_character_before_placeholder() -> Result<()> {
+        let mut template = TronTemplate::new("user@@@[host]@ @@[literal]@ @@@@@[x]@")?;
+        let mut names: Vec<_> = template.placeholders.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["host", "x"]);
+        template.set("host", "example.com")?;
+        template.set("x", "1")?;
+        assert_eq!(template.render()?, "user@example.com @[literal]@ @@1");
+
+        for text in ["me@", "@@[", "a@@@[b]@ c@"] {
+            assert_eq!(TronTemplate::unescape(&TronTemplate::escape(text)), text);
+        }
+
+        let mut backslash = TronTemplate::new(r"\\@[a]@ \@[b]@")?
+            .with_escape_style(EscapeStyle::Backslash)?;
+        backslash.set("a", "x")?;
+        assert_eq!(backslash.render()?, r"\x @[b]@");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedup_output_skips_identical_templates() -> Result<()> {
+        let helper = TronRef::new(TronTemplate::new("fn helper() {}")?);
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(helper.clone());
+        assembler.add_template(TronRef::new(TronTemplate::new("fn main() {}")?));
+        assembler.add_template(helper);
+
+        assert_eq!(assembler.render_each()?.len(), 3);
+
+        assembler.dedup_output(true);
+        assert_eq!(assembler.render_all()?, "fn helper() {}\nfn main() {}\n");
+        assert_eq!(assembler.render_each()?, vec!["fn helper() {}", "fn main() {}"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assembler_into_template_preserves_unset() -> Result<()> {
+        let mut header = TronRef::new(TronTemplate::new("// @[title]@ (@@[literal]@)")?);
+        header.set("title", "Generated")?;
+        let body = TronRef::new(TronTemplate::with_delimiters("fn {{ name }}() {}", "{{", "}}")?);
+
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(header);
+        assembler.add_template(body);
+
+        let mut combined = assembler.into_template("\n")?;
+        assert_eq!(combined.placeholders.keys().collect::<Vec<_>>(), vec!["name"]);
+
+        combined.set("name", "main")?;
+        assert_eq!(combined.render()?, "// Generated (@[literal]@)\nfn main() {}");
+
+        let mut user = TronRef::new(TronTemplate::new("@[user]@@[domain]@")?);
+        user.set("user", "me@")?;
+        let custom = TronRef::new(TronTemplate::with_delimiters("a@{{ b }}", "{{", "}}")?);
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(user);
+        assembler.add_template(custom);
+
+        let mut combined = assembler.into_template(" ")?;
+        let mut names: Vec<_> = combined.placeholders.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["b", "domain"]);
+        combined.set("domain", "example.com")?;
+        combined.set("b", "c")?;
+        assert_eq!(combined.render()?, "me@example.com a@c");
+
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(TronRef::new(TronTemplate::new("port @[port = 80]@")?));
+        let mut combined = assembler.into_template("")?;
+        assert_eq!(&*combined.content, "port @[port = 80]@");
+        assert_eq!(combined.render()?, "port 80");
+        combined.set("port", "8080")?;
+        assert_eq!(combined.render()?, "port 8080");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_accessors() -> Result<()> {
+        let error = TronTemplate::new("@[name]@")?.render().unwrap_err();
+        assert!(error.is_missing_placeholder());
+        assert_eq!(error.missing_placeholder_name(), Some("name"));
+        assert!(!error.is_execution_error());
+
+        let indexed = TronError::AtIndex {
+            index: 1,
+            source: Box::new(error),
+        };
+        assert_eq!(indexed.missing_placeholder_name(), Some("name"));
+
+        let execution = TronError::ExecutionError("failed".into());
+        assert!(execution.is_execution_error());
+        assert!(!execution.is_missing_placeholder());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_failed_reports_status_and_command() {
+        let exited = TronError::ProcessFailed {
+            command: "rust-script /tmp/script.rs".into(),
+            status: Some(101),
+            stderr: "error: oops".into(),
+        };
+        assert_eq!(
+            exited.to_string(),
+            "Execution error: `rust-script /tmp/script.rs` exited with status 101\nerror: oops"
+        );
+        assert!(exited.is_execution_error());
+        assert_eq!(exited.diagnostics().len(), 1);
+
+        let signalled = TronError::ProcessFailed {
+            command: "rust-script /tmp/script.rs".into(),
+            status: None,
+            stderr: String::new(),
+        };
+        assert!(signalled.to_string().contains("terminated by a signal"));
+        assert!(matches!(signalled, TronError::ProcessFailed { status: None, .. }));
+    }
+
+    #[test]
+    fn test_raw_include_is_not_parsed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("blob.txt"), "let s = \"@[not_a_placeholder]@\"; @[include:x]@")?;
+        let main = dir.path().join("main.tron");
+        fs::write(&main, "// @[title]@\n@[raw_include:blob.txt]@")?;
+
+        let mut template = TronTemplate::from_file(&main)?;
+        assert_eq!(template.placeholders.keys().collect::<Vec<_>>(), vec!["title"]);
+
+        template.set("title", "generated")?;
+        assert_eq!(
+            template.render()?,
+            "// generated\nlet s = \"@[not_a_placeholder]@\"; @[include:x]@"
+        );
+
+        fs::write(dir.path().join("contact.txt"), "contact@")?;
+        let address = dir.path().join("address.tron");
+        fs::write(&address, "@[raw_include:contact.txt]@@[domain]@")?;
+        let mut template = TronTemplate::from_file(&address)?;
+        assert_eq!(template.placeholders.keys().collect::<Vec<_>>(), vec!["domain"]);
+        template.set("domain", "example.com")?;
+        assert_eq!(template.render()?, "contact@example.com");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_is_cached_until_set() -> Result<()> {
+        let mut template = TronTemplate::new("hello @[name]@")?;
+        template.set("name", "world")?;
+        assert_eq!(template.render()?, "hello world");
+        assert_eq!(template.rendered.get().map(String::as_str), Some("hello world"));
+
+        template.set("name", "tron")?;
+        assert!(template.rendered.get().is_none());
+        assert_eq!(template.render()?, "hello tron");
+
+        let stamped = TronTemplate::new("@[__timestamp__]@")?;
+        stamped.render()?;
+        assert!(stamped.rendered.get().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_placeholders() -> Result<()> {
+        std::env::set_var("TRON_TEST_ENV_USER", "ferris");
+        let template = TronTemplate::new(
+            "@[env:TRON_TEST_ENV_USER]@ in @[env:TRON_TEST_ENV_UNSET:/tmp/work]@ @[name]@",
+        )?;
+        assert_eq!(template.placeholders.keys().collect::<Vec<_>>(), vec!["name"]);
+        assert_eq!(
+            template.env_placeholders(),
+            vec!["TRON_TEST_ENV_UNSET", "TRON_TEST_ENV_USER"]
+        );
+        assert_eq!(template.render_partial()?, "ferris in /tmp/work @[name]@");
+
+        let missing = TronTemplate::new("@[env:TRON_TEST_ENV_UNSET]@")?;
+        assert!(matches!(
+            missing.render(),
+            Err(TronError::MissingEnvVar(name)) if name == "TRON_TEST_ENV_UNSET"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_strict_rejects_leftover_delimiters() -> Result<()> {
+        let mut template = TronTemplate::new("let x = @[value]@;")?;
+        template.set("value", "1")?;
+        assert_eq!(template.render_strict()?, "let x = 1;");
+
+        template.set("value", "oops ]@")?;
+        assert!(matches!(template.render_strict(), Err(TronError::InvalidSyntax(_))));
+
+        let unclosed = TronTemplate::new("let x = @[value;")?;
+        assert!(matches!(unclosed.render_strict(), Err(TronError::InvalidSyntax(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assembler_shared_values_with_local_overrides() -> Result<()> {
+        let mut assembler = TronAssembler::new();
+        assembler.share_values(true);
+        assembler.add_template(TronRef::new(TronTemplate::new("struct @[name]@;")?));
+
+        let mut local = TronTemplate::new("impl @[name]@ for @[target]@ {}")?;
+        local.set("name", "Display")?;
+        assembler.add_template(TronRef::new(local));
+
+        assembler.set_global("name", "Point")?;
+        assembler.set_global("target", "Point")?;
+        assert_eq!(assembler.render_all()?, "struct Point;\nimpl Display for Point {}\n");
+
+        // Shared values stay in the store rather than being copied into members
+        assert!(assembler.templates[0].inner().placeholders["name"].is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_ref_depth_limit() -> Result<()> {
+        let mut chain = TronRef::new(TronTemplate::new("leaf")?);
+        for _ in 0..DEFAULT_MAX_DEPTH {
+            let mut parent = TronRef::new(TronTemplate::new("(@[child]@)")?);
+            parent.set_ref("child", chain)?;
+            chain = parent;
+        }
+        assert_eq!(chain.depth(), DEFAULT_MAX_DEPTH);
+
+        let mut too_deep = TronRef::new(TronTemplate::new("@[child]@")?);
+        assert!(matches!(
+            too_deep.set_ref("child", chain.clone()),
+            Err(TronError::DepthExceeded { depth: 65, limit: 64 })
+        ));
+
+        let mut shallow = TronRef::new(TronTemplate::new("@[child]@")?).with_max_depth(1);
+        let mut middle = TronRef::new(TronTemplate::new("@[child]@")?);
+        middle.set_ref("child", TronRef::new(TronTemplate::new("leaf")?))?;
+        assert!(shallow.set_ref("child", middle).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_styles() -> Result<()> {
+        let content = r"@@[a]@ \@[b]@ @[c]@";
+
+        let double = TronTemplate::new(content)?;
+        let mut names: Vec<&String> = double.placeholders.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["b", "c"]);
+        assert_eq!(double.render_partial()?, r"@[a]@ \@[b]@ @[c]@");
+
+        let mut backslash = TronTemplate::new(content)?.with_escape_style(EscapeStyle::Backslash)?;
+        let mut names: Vec<&String> = backslash.placeholders.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["a", "c"]);
+        backslash.set("a", "x")?;
+        backslash.set("c", "y")?;
+        assert_eq!(backslash.render()?, "@x @[b]@ y");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_file_with_delimiters() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("header.tron"), "// {{ title }}\n")?;
+        let path = dir.path().join("main.tron");
+        fs::write(&path, "{{ include:header.tron }}fn {{ name }}() {}")?;
+
+        let mut template = TronTemplate::from_file_with_delimiters(&path, "{{", "}}")?;
+        assert_eq!(template.delimiters(), ("{{", "}}"));
+        assert_eq!(template.path.as_deref(), Some(path.as_path()));
+
+        template.set("title", "generated")?;
+        template.set("name", "main")?;
+        assert_eq!(template.render()?, "// generated\nfn main() {}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_to_fmt() -> Result<()> {
+        let mut template = TronTemplate::new("fn @[name]@() {}")?;
+        template.set("name", "main")?;
+
+        let mut output = String::from("// generated\n");
+        template.render_to_fmt(&mut output)?;
+        assert_eq!(output, "// generated\nfn main() {}");
+
+        let unset = TronTemplate::new("@[name]@")?;
+        assert!(unset.render_to_fmt(&mut String::new()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assembler_clear_and_reset_all() -> Result<()> {
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(TronRef::new(TronTemplate::new("mod @[name]@;")?));
+        assembler.add_template(TronRef::new(TronTemplate::new("use @[name]@::*;")?));
+        assembler.set_global("name", "first")?;
+        assert_eq!(assembler.render_all()?, "mod first;\nuse first::*;\n");
+
+        assembler.reset_all();
+        assert!(matches!(assembler.render_all(), Err(TronError::MissingPlaceholder(_))));
+        assembler.set_global("name", "second")?;
+        assert_eq!(assembler.render_all()?, "mod second;\nuse second::*;\n");
+
+        assembler.clear();
+        assert_eq!(assembler.render_all()?, "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_preview_truncates_values() -> Result<()> {
+        let mut template = TronTemplate::new("@[body]@ @[name]@ @[unset]@")?;
+        template.set("body", "fn main() {\n    println!(\"hello\");\n}")?;
+        template.set("name", "main")?;
+
+        assert_eq!(
+            template.preview(12),
+            "body = \"fn main() { \"…\nname = \"main\"\nunset = <unset>"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_placeholder_registers_orphans() -> Result<()> {
+        let mut template = TronTemplate::new("fn @[name]@() {}")?;
+        template.with_placeholder("body", "todo!()")?;
+        template.with_placeholder("name", "main")?;
+
+        assert_eq!(template.placeholders["body"], "todo!()");
+        assert_eq!(template.render()?, "fn main() {}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_with_source() -> Result<()> {
+        struct Config;
+        impl ValueSource for Config {
+            fn get(&self, name: &str) -> Option<String> {
+                (name == "port").then(|| "8080".to_string())
+            }
+        }
+
+        let mut template = TronTemplate::new("@[host]@:@[port]@")?;
+        template.set("host", "localhost")?;
+        assert_eq!(template.render_with_source(&Config)?, "localhost:8080");
+
+        let closure = |name: &str| Some(name.to_uppercase());
+        assert_eq!(template.render_with_source(&closure)?, "localhost:PORT");
+
+        let map = HashMap::from([("host".to_string(), "ignored".to_string())]);
+        assert!(matches!(
+            template.render_with_source(&map),
+            Err(TronError::MissingPlaceholder(name)) if name == "port"
+        ));
+
+        std::env::set_var("TRON_TEST_SOURCE_PORT", "9000");
+        let env = TronTemplate::new("@[TRON_TEST_SOURCE_PORT]@")?;
+        assert_eq!(env.render_with_source(&EnvSource)?, "9000");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cloned_ref_is_independent() -> Result<()> {
+        let mut child = TronRef::new(TronTemplate::new("@[value]@")?);
+        child.set("value", "child")?;
+
+        let mut original = TronRef::new(TronTemplate::new("@[name]@ @[child]@")?)
+            .with_dependency("serde = \"1.0\"");
+        original.set("name", "original")?;
+        original.set_ref("child", child.clone())?;
+
+        let mut clone = original.clone().with_dependency("rand = \"0.8\"");
+        clone.set("name", "clone")?;
+        child.set("value", "changed")?;
+        clone.set_ref("child", child)?;
+
+        assert_eq!(original.render()?, "original child");
+        assert_eq!(original.dependencies, vec!["serde = \"1.0\""]);
+        assert_eq!(clone.render()?, "clone changed");
+        assert_eq!(clone.dependencies.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_required_placeholders_skip_defaults() -> Result<()> {
+        let mut template =
+            TronTemplate::new("@[host = localhost]@:@[port = 8080]@/@[path]@?@[query]@ @[user]@")?;
+        assert_eq!(template.required_placeholders(), vec!["path", "query", "user"]);
+
+        template.set("query", "q")?;
+        template.set("port", "9000")?;
+        assert_eq!(template.required_placeholders(), vec!["path", "user"]);
+
+        template.set("path", "index")?;
+        template.set("user", "ferris")?;
+        assert_eq!(template.render()?, "localhost:9000/index?q ferris");
+        assert_eq!(template.compile().render(&HashMap::from([
+            ("path".to_string(), "p".to_string()),
+            ("query".to_string(), "x".to_string()),
+            ("user".to_string(), "u".to_string()),
+        ]))?, "localhost:8080/p?x u");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_placeholder_defaults() -> Result<()> {
+        let mut template = TronTemplate::new("@[host = localhost]@:@[port = 8080]@ @[port = 80]@ @[path]@")?;
+        template.set("host", "example.com")?;
+
+        let defaults = template.placeholder_defaults();
+        assert_eq!(defaults.len(), 2);
+        assert_eq!(defaults["host"], "localhost");
+        assert_eq!(defaults["port"], "8080");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "execute")]
+    #[test]
+    fn test_attach_script_wraps_process_failures() {
+        let failure = || TronError::ProcessFailed {
+            command: "rust-script main.rs".into(),
+            status: Some(1),
+            stderr: "error: boom".into(),
+        };
+
+        assert!(matches!(attach_script(failure(), None), TronError::ProcessFailed { .. }));
+        assert!(matches!(
+            attach_script(failure(), Some("fn main() {}")),
+            TronError::ScriptFailed { message, script }
+                if message.contains("exited with status 1") && script == "fn main() {}"
+        ));
+    }
+
+    #[test]
+    fn test_is_placeholder_set() -> Result<()> {
+        let mut template = TronTemplate::new("@[name]@: @[kind]@")?;
+        template.set("name", "main")?;
+
+        assert!(template.is_placeholder_set("name")?);
+        assert!(!template.is_placeholder_set("kind")?);
+        assert!(matches!(
+            template.is_placeholder_set("other"),
+            Err(TronError::MissingPlaceholder(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_partial_bakes_values() -> Result<()> {
+        let template = TronTemplate::new("fn @[name]@() -> @[ty]@ { @[name]@_impl() }")?;
+        let values = HashMap::from([("name".to_string(), "make_@[ty]@".to_string())]);
+
+        let mut partial = template.with_partial(&values)?;
+        assert_eq!(partial.placeholders.keys().collect::<Vec<_>>(), vec!["ty"]);
+        partial.set("ty", "u32")?;
+        assert_eq!(partial.render()?, "fn make_@[ty]@() -> u32 { make_@[ty]@_impl() }");
+
+        let unknown = HashMap::from([("other".to_string(), "x".to_string())]);
+        assert!(template.with_partial(&unknown).is_err());
+
+        let address = TronTemplate::new("@[user]@@[domain]@")?;
+        let values = HashMap::from([("user".to_string(), "me@".to_string())]);
+        let mut partial = address.with_partial(&values)?;
+        assert_eq!(partial.placeholders.keys().collect::<Vec<_>>(), vec!["domain"]);
+        partial.set("domain", "example.com")?;
+        assert_eq!(partial.render()?, "me@example.com");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_ref_indented() -> Result<()> {
+        let body = TronRef::new(TronTemplate::new("let x = 1;\n\nprintln!(\"{}\", x);")?);
+
+        let mut function = TronRef::new(TronTemplate::new("fn main() {\n    @[body]@\n}")?);
+        function.set_ref_indented("body", body)?;
+
+        assert_eq!(
+            function.render()?,
+            "fn main() {\n    let x = 1;\n\n    println!(\"{}\", x);\n}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_ref_template() -> Result<()> {
+        let mut statement = TronTemplate::new("return @[value]@;")?;
+        statement.set("value", "42")?;
+
+        let mut function = TronRef::new(TronTemplate::new("fn answer() -> u32 { @[body]@ }")?);
+        function.set_ref_template("body", &statement)?;
+
+        assert_eq!(function.render()?, "fn answer() -> u32 { return 42; }");
+        assert_eq!(function.depth(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_syntax_reports_position() {
+        assert!(TronTemplate::validate_syntax("fn @[name]@() {}\n@@[literal]@").is_ok());
+
+        let error = TronTemplate::validate_syntax("fn main() {\n    let @[name = 1;\n}").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid template syntax: unclosed `@[` at line 2, column 9"
+        );
+
+        assert!(matches!(
+            TronTemplate::validate_syntax("value]@"),
+            Err(TronError::InvalidSyntax(message)) if message.contains("unopened `]@` at line 1, column 6")
+        ));
+
+        assert!(TronTemplate::validate_syntax("a @[include:part.tpl]@ b @[raw_include:x]@").is_ok());
+
+        let error =
+            TronTemplate::validate_syntax("@[extends base.tpl]@\n@[block body]@ never closed")
+                .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid template syntax: block is never closed at line 2, column 1"
+        );
+        assert!(TronTemplate::validate_syntax("@[block body]@ is a placeholder").is_ok());
+    }
+
+    #[test]
+    fn test_assert_unique() -> Result<()> {
+        let template = TronTemplate::new("mod @[module]@; use @[module]@::@[item]@;")?;
+
+        assert!(template.assert_unique("item").is_ok());
+        assert!(matches!(
+            template.assert_unique("module"),
+            Err(TronError::InvalidSyntax(message)) if message.contains("occurs 2 times")
+        ));
+        assert!(matches!(
+            template.assert_unique("other"),
+            Err(TronError::MissingPlaceholder(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_with_resolver_is_lazy() -> Result<()> {
+        struct Counting(std::cell::Cell<usize>);
+        impl PlaceholderResolver for Counting {
+            fn resolve(&self, name: &str) -> Option<String> {
+                self.0.set(self.0.get() + 1);
+                Some(format!("<{}>", name))
+            }
+        }
+
+        let mut template = TronTemplate::new("@[a]@ @[b]@ @[c]@")?;
+        template.set("a", "set")?;
+        let resolver = Counting(std::cell::Cell::new(0));
+
+        assert_eq!(template.render_with_resolver(&resolver)?, "set <b> <c>");
+        assert_eq!(resolver.0.get(), 2);
+
+        let map = HashMap::from([("b".to_string(), "B".to_string()), ("c".to_string(), "C".to_string())]);
+        assert_eq!(template.render_with_resolver(&map)?, "set B C");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_bounded() -> Result<()> {
+        let mut template = TronTemplate::new("header @[body]@ footer")?;
+        template.set("body", &"x".repeat(100))?;
+
+        assert_eq!(template.render_bounded(200)?.len(), 114);
+        assert!(matches!(
+            template.render_bounded(50),
+            Err(TronError::ExecutionError(message)) if message.contains("50 bytes")
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_placeholder_with_literal() -> Result<()> {
+        let mut template = TronTemplate::new("struct @[name]@ { @[field]@: @[name]@Id }")?;
+        template.set("field", "id")?;
+        template.replace_placeholder_with_literal("name", "User@[x]@")?;
+
+        assert_eq!(&*template.content, "struct User@@[x]@ { @[field]@: User@@[x]@Id }");
+        assert_eq!(template.placeholders.keys().collect::<Vec<_>>(), vec!["field"]);
+        assert_eq!(template.render()?, "struct User@[x]@ { id: User@[x]@Id }");
+        assert!(template.replace_placeholder_with_literal("name", "again").is_err());
+
+        let mut address = TronTemplate::new("@[user]@@[domain]@")?;
+        address.replace_placeholder_with_literal("user", "me@")?;
+        assert_eq!(address.placeholders.keys().collect::<Vec<_>>(), vec!["domain"]);
+        address.set("domain", "example.com")?;
+        assert_eq!(address.render()?, "me@example.com");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_fmt_in_display() -> Result<()> {
+        struct Wrapper(TronTemplate);
+        impl fmt::Display for Wrapper {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.render_fmt(f)
+            }
+        }
+
+        let mut template = TronTemplate::new("fn @[name]@() {}")?;
+        let mut output = String::new();
+        assert!(fmt::write(&mut output, format_args!("{}", Wrapper(template.clone()))).is_err());
+        assert!(output.is_empty());
+
+        template.set("name", "main")?;
+        assert_eq!(Wrapper(template).to_string(), "fn main() {}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assembler_retain_complete() -> Result<()> {
+        let mut done = TronTemplate::new("mod @[name]@;")?;
+        done.set("name", "a")?;
+
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(TronRef::new(done));
+        assembler.add_template(TronRef::new(TronTemplate::new("mod @[name]@;")?));
+        assembler.add_template(TronRef::new(TronTemplate::new("mod @[name = b]@;")?));
+
+        assembler.retain(|t| t.inner().is_complete());
+        assert_eq!(assembler.render_all()?, "mod a;\nmod b;\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_pool_shares_content() -> Result<()> {
+        let mut pool = TemplatePool::new();
+        let mut first = pool.get("let @[name]@ = 1;")?;
+        let second = pool.get("let @[name]@ = 1;")?;
+        pool.get("let @[other]@ = 2;")?;
+
+        assert_eq!(pool.len(), 2);
+        assert!(Arc::ptr_eq(&first.content, &second.content));
+        assert!(Arc::ptr_eq(&first.content, &first.clone().content));
+
+        first.set("name", "x")?;
+        assert_eq!(first.render()?, "let x = 1;");
+        assert!(!second.is_placeholder_set("name")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_content() {
+        let error = TronTemplate::new("fn main() {\n    let x = @[ ]@;\n}").unwrap_err();
+        assert!(matches!(
+            &error,
+            TronError::ParseAt { line: 2, column: 13, line_text, .. } if line_text == "    let x = @[ ]@;"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Parse error at line 2, column 13: empty placeholder name\n    let x = @[ ]@;\n            ^"
+        );
+
+        assert!(TronTemplate::new("@[env:]@").is_err());
+        assert!(TronTemplate::new("@[# just a comment ]@ @[ = default]@").is_err());
+    }
+
+    /// Drive a future to completion on the current thread
+    #[cfg(all(unix, feature = "execute"))]
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        struct Unpark(std::thread::Thread);
+
+        impl std::task::Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = std::task::Waker::from(Arc::new(Unpark(std::thread::current())));
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                std::task::Poll::Ready(output) => return output,
+                std::task::Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[cfg(all(unix, feature = "execute"))]
+    #[test]
+    fn test_execute_with_stub_runner() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let runner = dir.path().join("fake-rust-script");
+        fs::write(&runner, "#!/bin/sh\nshift\necho \"$@\"\necho done\n")?;
+        fs::set_permissions(&runner, fs::Permissions::from_mode(0o755))?;
+
+        let template_ref = TronRef::new(TronTemplate::new("fn main() {}")?);
+        let options = template_ref.execution().with_runner(&runner).with_args(["a", "b"]);
+        assert_eq!(options.run_blocking()?, "a b\ndone\n");
+        assert_eq!(block_on(options.run())?, "a b\ndone\n");
+
+        let lines: Vec<String> = options.run_streaming()?.collect::<Result<_>>()?;
+        assert_eq!(lines, vec!["a b", "done"]);
+
+        let failing = dir.path().join("failing-rust-script");
+        fs::write(&failing, "#!/bin/sh\necho partial\necho oops >&2\nexit 3\n")?;
+        fs::set_permissions(&failing, fs::Permissions::from_mode(0o755))?;
+        let options = template_ref.execution().with_runner(&failing);
+        assert!(matches!(
+            options.run_blocking(),
+            Err(TronError::ProcessFailed { status: Some(3), stderr, .. }) if stderr == "oops\n"
+        ));
+        let mut lines = options.run_streaming()?;
+        assert_eq!(lines.next().transpose()?, Some("partial".to_string()));
+        assert!(matches!(lines.next(), Some(Err(TronError::ProcessFailed { status: Some(3), .. }))));
+        assert!(lines.next().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sections() -> Result<()> {
+        let mut template = TronTemplate::new(
+            "@[section:imports]@use std::fmt;@[endsection]@\n\
+             @[section:body]@fn @[name]@() {}@[endsection]@",
+        )?;
+
+        assert_eq!(template.sections(), vec!["imports", "body"]);
+        assert_eq!(template.section("body"), Some("fn @[name]@() {}"));
+        assert_eq!(template.section("missing"), None);
+        assert_eq!(template.placeholders.keys().collect::<Vec<_>>(), vec!["name"]);
+
+        template.set("name", "main")?;
+        assert_eq!(template.render()?, "use std::fmt;\nfn main() {}");
+
+        assert!(TronTemplate::validate_syntax("@[section:a]@ open").is_err());
+        let stray = TronTemplate::new("closed @[endsection]@")?;
+        assert_eq!(stray.placeholders.keys().collect::<Vec<_>>(), vec!["endsection"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_substitute_once() -> Result<()> {
+        let mut template = TronTemplate::new("@[a]@ + @[b]@")?;
+        template.set("a", "1")?;
+        template.set("b", "2")?;
+
+        let step = template.substitute_once("a")?;
+        assert_eq!(&*step.content, "1 + @[b]@");
+        assert_eq!(step.substitute_once("b")?.render()?, "1 + 2");
+
+        assert!(TronTemplate::new("@[a]@")?.substitute_once("a").is_err());
+        assert!(template.substitute_once("c").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_inheritance() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(
+            dir.path().join("base.tron"),
+            "// @[block header]@generated@[end]@\nfn @[name]@() {\n@[block body]@    todo!()\n@[end]@}",
+        )?;
+        let child = dir.path().join("child.tron");
+        fs::write(
+            &child,
+            "@[extends base.tron]@\nignored\n@[block body]@    println!(\"@[message]@\");\n@[end]@",
+        )?;
+
+        let mut template = TronTemplate::from_file(&child)?;
+        let mut names: Vec<&String> = template.placeholders.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["message", "name"]);
+
+        template.set("name", "main")?;
+        template.set("message", "hi")?;
+        assert_eq!(
+            template.render()?,
+            "// generated\nfn main() {\n    println!(\"hi\");\n}"
+        );
+
+        let unclosed = TronTemplate::new("@[block a]@ never closed")?;
+        assert_eq!(unclosed.placeholders.keys().collect::<Vec<_>>(), vec!["block a"]);
+        fs::write(&child, "@[extends base.tron]@\n@[block body]@ never closed")?;
+        assert!(TronTemplate::from_file(&child).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_end_is_a_placeholder_outside_blocks() -> Result<()> {
+        let mut template = TronTemplate::new("for i in @[start]@..@[end]@ {}")?;
+        template.set("start", "0")?;
+        template.set("end", "10")?;
+        assert_eq!(template.render()?, "for i in 0..10 {}");
+        assert!(TronTemplate::validate_syntax("for i in @[start]@..@[end]@ {}").is_ok());
+
+        let mut block = TronTemplate::new("@[block body]@@[x]@@[end]@ @[end]@")?;
+        let mut names: Vec<_> = block.placeholders.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["end", "x"]);
+        block.set("x", "1")?;
+        block.set("end", "2")?;
+        assert_eq!(block.render()?, "1 2");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_member_placeholders() -> Result<()> {
+        let mut header = TronRef::new(TronTemplate::new("// @[title]@")?).with_name("header");
+        header.set("title", "generated")?;
+        let body = TronRef::new(TronTemplate::new("@[member:header]@\nfn main() {}")?);
+
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(body);
+        assembler.add_template(header);
+        assert_eq!(assembler.render_all()?, "// generated\nfn main() {}\n// generated\n");
+
+        let mut cyclic = TronAssembler::new();
+        cyclic.add_template(TronRef::new(TronTemplate::new("a @[member:b]@")?).with_name("a"));
+        cyclic.add_template(TronRef::new(TronTemplate::new("b @[member:a]@")?).with_name("b"));
+        match cyclic.render_all() {
+            Err(error @ TronError::CyclicComposition(_)) => {
+                assert_eq!(error.to_string(), "Cyclic composition: a -> b -> a");
+            }
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ref_render_with_context() -> Result<()> {
+        let mut greeting = TronRef::new(TronTemplate::new("@[greeting]@, @[name]@!")?);
+        greeting.set("greeting", "Hello")?;
+
+        let ctx: HashMap<String, String> = [("greeting", "Hi"), ("name", "Ada")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(greeting.render_with_context(&ctx)?, "Hello, Ada!");
+        assert!(!greeting.inner().is_placeholder_set("name")?);
+        assert!(greeting.render_with_context(&HashMap::new()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_macro() -> Result<()> {
+        assert_eq!(
+            generate!("fn @[name]@() -> u32 { @[value]@ }", name = "answer", value = 42)?,
+            "fn answer() -> u32 { 42 }"
+        );
+        assert_eq!(generate!("no placeholders")?, "no placeholders");
+        assert_eq!(generate!("@[- name = x -]@", name = "y")?, "y");
+
+        let content = String::from("fn @[name]@() {}");
+        assert!(matches!(
+            generate!(&content, name = "main", typo = "x"),
+            Err(TronError::MissingPlaceholder(name)) if name == "typo"
+        ));
+
+        assert!(__has_placeholder("@[ a = 1 ]@ @[b]@", "a"));
+        assert!(__has_placeholder("@[ a = 1 ]@ @[b]@", "b"));
+        assert!(!__has_placeholder("@[ab]@ @[c", "a"));
+        assert!(!__has_placeholder("@[a @[b]@", "a"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_canonicalizes_tags() -> Result<()> {
+        let mut template = TronTemplate::new("fn main() {\n    @[ body ]@\n}@[# note ]@ @[-  tail=x -]@")?;
+        template.set("body", "run();")?;
+        let before = template.render()?;
+
+        template.normalize();
+        assert_eq!(&*template.content, "fn main() {\n    @[body]@\n}@[# note ]@ @[-tail = x-]@");
+        assert_eq!(template.render()?, before);
+        assert_eq!(before, "fn main() {\n    run();\n}x");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_byte_metrics() -> Result<()> {
+        let template = TronTemplate::new("fn @[name]@() { @[ body ]@ }")?;
+        assert_eq!(template.byte_len(), 28);
+        assert_eq!(template.placeholder_byte_overhead(), "@[name]@".len() + "@[ body ]@".len());
+        assert_eq!(TronTemplate::new("plain")?.placeholder_byte_overhead(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spaced_placeholders_are_substituted() -> Result<()> {
+        let mut template = TronTemplate::new("fn @[ name ]@() { @[body ]@ }")?;
+        template.set("name", "main")?;
+        template.set("body", "run()")?;
+
+        assert_eq!(template.render()?, "fn main() { run() }");
+        assert_eq!(template.render_partial()?, "fn main() { run() }");
+        assert_eq!(template.with_partial(&HashMap::new())?.render()?, "fn main() { run() }");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_path() -> Result<()> {
+        let template = TronTemplate::new("// @[__template__]@ from @[__file__]@")?;
+        assert!(matches!(
+            template.render(),
+            Err(TronError::MissingPlaceholder(name)) if name == "__template__"
+        ));
+
+        let template = template.with_path("templates/main.tron");
+        assert_eq!(template.path.as_deref(), Some(Path::new("templates/main.tron")));
+        assert_eq!(template.render()?, "// main.tron from templates/main.tron");
+        assert!(format!("{:?}", template).contains("path: \"templates/main.tron\""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_ref_default() -> Result<()> {
+        let mut page = TronRef::new(TronTemplate::new("<main>@[footer]@</main>")?);
+        let footer = TronRef::new(TronTemplate::new("<footer>default</footer>")?).with_dependency("serde = \"1\"");
+        page.set_ref_default("footer", footer)?;
+
+        assert_eq!(page.render()?, "<main><footer>default</footer></main>");
+        assert_eq!(page.checked_dependencies()?, vec!["serde = \"1\""]);
+        assert_eq!(page.inner().render_partial()?, "<main>@[footer]@</main>");
+
+        let mut custom = page.clone();
+        custom.set("footer", "<footer>custom</footer>")?;
+        assert_eq!(custom.render()?, "<main><footer>custom</footer></main>");
+        assert!(custom.checked_dependencies()?.is_empty());
+
+        let mut plain = page.clone();
+        plain.inner_mut().replace_placeholder_with_literal("footer", "")?;
+        assert_eq!(plain.render()?, "<main></main>");
+        assert!(plain.checked_dependencies()?.is_empty());
+        assert_eq!(plain.composition_stats().node_count, 1);
+
+        let mut outer = TronRef::new(TronTemplate::new("<body>@[page]@</body>")?);
+        outer.set_ref("page", page)?;
+        assert_eq!(outer.render()?, "<body><main><footer>default</footer></main></body>");
+        assert_eq!(outer.dependencies, vec!["serde = \"1\""]);
+
+        assert!(matches!(
+            TronRef::new(TronTemplate::new("x")?).set_ref_default("missing", custom),
+            Err(TronError::MissingPlaceholder(name)) if name == "missing"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_escaped_html() -> Result<()> {
+        let mut template = TronTemplate::new("<p title=\"@[title]@\">@[body]@</p>@[footer]@")?;
+        template.set("title", "Tom & \"Jerry\"")?;
+        template.set("body", "<script>alert('x')</script>")?;
+        template.set_raw("footer", "<hr>")?;
+
+        assert_eq!(
+            template.render_escaped(EscapeMode::Html)?,
+            "<p title=\"Tom &amp; &quot;Jerry&quot;\">&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;</p><hr>"
+        );
+        assert_eq!(template.render_escaped(EscapeMode::None)?, template.render()?);
+
+        template.set("footer", "<hr>")?;
+        assert!(template.render_escaped(EscapeMode::Html)?.ends_with("&lt;hr&gt;"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_conditional_dependency() -> Result<()> {
+        let mut script = TronRef::new(TronTemplate::new("fn main() {}")?)
+            .with_dependency("anyhow = \"1\"")
+            .with_conditional_dependency("serde = \"1\"", "use_serde");
+
+        let manifest = script.to_cargo_manifest("demo")?;
+        assert!(manifest.contains("anyhow = \"1\""));
+        assert!(!manifest.contains("serde"));
+
+        script.set_flag("use_serde", true);
+        assert!(script.to_cargo_manifest("demo")?.ends_with("anyhow = \"1\"\nserde = \"1\"\n"));
+        assert_eq!(script.render()?, "fn main() {}");
+        assert!(script.inner().placeholders.is_empty());
+
+        script.set_flag("use_serde", false);
+        assert!(!script.to_cargo_manifest("demo")?.contains("serde"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assembler_find() -> Result<()> {
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(TronRef::new(TronTemplate::new("// header")?).with_name("header"));
+        assembler.add_template(TronRef::new(TronTemplate::new("fn @[name]@() {}")?).with_name("first"));
+        assembler.add_template(TronRef::new(TronTemplate::new("fn @[name]@() {}")?).with_name("second"));
+
+        let found = assembler.find(|t| !t.inner().placeholder_uses("name").is_empty());
+        assert_eq!(found.and_then(TronRef::name), Some("first"));
+        assert!(assembler.find(|t| t.name() == Some("missing")).is_none());
+
+        assembler
+            .find_mut(|t| t.name() == Some("second"))
+            .expect("second template")
+            .set("name", "helper")?;
+        assert!(assembler.render_each().is_err());
+        assembler.find_mut(|t| t.name() == Some("first")).expect("first template").set("name", "main")?;
+        assert_eq!(assembler.render_all()?, "// header\nfn main() {}\nfn helper() {}\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_placeholder_lines() -> Result<()> {
+        let template = TronTemplate::new("fn @[name]@() {\n    @[# note ]@\n    @[body]@ @[body]@ @[name]@\n}\n@[name]@")?;
+
+        assert_eq!(
+            template.placeholder_lines(),
+            vec![
+                ("name".to_string(), 1),
+                ("body".to_string(), 3),
+                ("name".to_string(), 3),
+                ("name".to_string(), 5),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_with_report() -> Result<()> {
+        let mut template = TronTemplate::new("@[greeting]@ @[name]@, @[greeting]@ @[punctuation = !]@ @[__version__]@")?;
+        template.set("name", "Ada")?;
+        template.set("greeting", "hi")?;
+
+        template.with_placeholder("unused", "x")?;
+
+        let (rendered, report) = template.render_with_report()?;
+        assert_eq!(rendered, format!("hi Ada, hi ! {}", env!("CARGO_PKG_VERSION")));
+        assert_eq!(report.substituted, vec!["greeting", "name"]);
+        assert_eq!(report.unused, vec!["unused"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_ref_if_present() -> Result<()> {
+        let child = TronRef::new(TronTemplate::new("run();")?).with_dependency("serde = \"1\"");
+
+        let mut with_body = TronRef::new(TronTemplate::new("fn main() { @[body]@ }")?);
+        assert!(with_body.set_ref_if_present("body", child.clone())?);
+        assert_eq!(with_body.render()?, "fn main() { run(); }");
+        assert_eq!(with_body.dependencies, vec!["serde = \"1\""]);
+
+        let mut without_body = TronRef::new(TronTemplate::new("fn main() {}")?);
+        assert!(!without_body.set_ref_if_present("body", child)?);
+        assert!(without_body.dependencies.is_empty());
+
+        let unrendered = TronRef::new(TronTemplate::new("@[unset]@")?);
+        assert!(with_body.set_ref_if_present("body", unrendered).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_checked() -> Result<()> {
+        let mut template = TronTemplate::new("fn @[name]@() {}")?;
+        template.with_placeholder("later", "")?;
+
+        template.set_checked("name", "main")?;
+        assert!(matches!(
+            template.set_checked("later", "value"),
+            Err(TronError::UnusedPlaceholder(name)) if name == "later"
+        ));
+        assert!(!template.is_placeholder_set("later")?);
+        assert!(matches!(
+            template.set_checked("missing", "value"),
+            Err(TronError::MissingPlaceholder(_))
+        ));
+        assert_eq!(template.render()?, "fn main() {}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_unknown_sets() -> Result<()> {
+        let mut template = TronTemplate::new("fn @[name]@() {}")?;
+        assert!(template.set("unknown", "value").is_err());
+
+        template.ignore_unknown_sets(true);
+        template.set("unknown", "value")?;
+        template.set_raw("unknown", "value")?;
+        template.set("name", "main")?;
+        assert!(!template.placeholders.contains_key("unknown"));
+        assert_eq!(template.render()?, "fn main() {}");
+
+        template.ignore_unknown_sets(false);
+        assert!(matches!(
+            template.set("unknown", "value"),
+            Err(TronError::MissingPlaceholder(name)) if name == "unknown"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_limited() -> Result<()> {
+        let mut template = TronTemplate::new("@[x]@ @[x]@ @[x]@")?;
+        template.set_limited("x", "first", 1)?;
+        assert_eq!(template.render()?, "first @[x]@ @[x]@");
+
+        template.set_limited("x", "v", 2)?;
+        assert_eq!(template.render()?, "v v @[x]@");
+
+        template.set("x", "all")?;
+        assert_eq!(template.render()?, "all all all");
+
+        let mut limited = TronRef::new(TronTemplate::new("@[x]@ @[x]@")?);
+        limited.inner_mut().set_limited("x", "first", 1)?;
+        assert_eq!(limited.inner().substitute_once("x")?.render()?, "first @[x]@");
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(limited);
+        let flattened = assembler.into_template("")?;
+        assert!(flattened.placeholders.is_empty());
+        assert_eq!(flattened.render()?, "first @[x]@");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_embedded() -> Result<()> {
+        let template = TronTemplate::from_embedded("// @[__template__]@", "templates/header.tron")?;
+        assert_eq!(template.path.as_deref(), Some(Path::new("templates/header.tron")));
+        assert_eq!(template.render()?, "// header.tron");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_composition_stats() -> Result<()> {
+        let mut leaf = TronRef::new(TronTemplate::new("println!(\"@[message]@\");")?);
+        leaf.set("message", "hi")?;
+        let mut function = TronRef::new(TronTemplate::new("fn main() { @[body]@ @[extra]@ }")?);
+        function.set_ref("body", leaf.clone())?;
+        function.set_ref("extra", leaf)?;
+        let mut module = TronRef::new(TronTemplate::new("mod m { @[item]@ }")?);
+        module.set_ref("item", function)?;
+
+        assert_eq!(
+            module.composition_stats(),
+            CompositionStats {
+                depth: 2,
+                node_count: 4,
+            }
+        );
+
+        module.set("item", "")?;
+        assert_eq!(module.composition_stats().node_count, 1);
+
+        let mut leaf = TronRef::new(TronTemplate::new("@[x]@")?);
+        leaf.set("x", "leaf")?;
+        let mut mid = TronRef::new(TronTemplate::new("(@[x]@)")?);
+        mid.set_ref("x", leaf)?;
+        let mut top = TronRef::new(TronTemplate::new("[@[x]@ @[y]@]")?);
+        top.set_ref("y", mid)?;
+        assert_eq!(
+            top.composition_stats(),
+            CompositionStats {
+                depth: 2,
+                node_count: 3,
             }
+        );
+        top.set("y", "plain")?;
+        assert_eq!(
+            top.composition_stats(),
+            CompositionStats {
+                depth: 0,
+                node_count: 1,
+            }
+        );
+        assert_eq!(top.depth(), 2);
+
+        let mut function = TronRef::new(TronTemplate::new("fn main() { @[body]@ }")?);
+        function.set_ref("body", TronRef::new(TronTemplate::new("todo!()")?))?;
+        assert_eq!(function.composition_stats().node_count, 2);
+        function.inner_mut().reset();
+        assert_eq!(function.composition_stats().node_count, 1);
+        function.set_ref("body", TronRef::new(TronTemplate::new("todo!()")?))?;
+        function.inner_mut().set("body", "loop {}")?;
+        assert_eq!(function.composition_stats().node_count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cyclic_composition_reports_only_the_loop() -> Result<()> {
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(TronRef::new(TronTemplate::new("@[member:loop_a]@")?).with_name("entry"));
+        assembler.add_template(TronRef::new(TronTemplate::new("@[member:loop_b]@")?).with_name("loop_a"));
+        assembler.add_template(TronRef::new(TronTemplate::new("@[member:loop_a]@")?).with_name("loop_b"));
+
+        match assembler.render_each() {
+            Err(TronError::AtIndex { index: 0, source }) => assert!(matches!(
+                *source,
+                TronError::CyclicComposition(ref cycle) if cycle == &["loop_a", "loop_b", "loop_a"]
+            )),
+            other => panic!("expected a cycle, got {:?}", other),
         }
+
         Ok(())
     }
 
-    /// Render all templates and combine them
-    pub fn render_all(&self) -> Result<String> {
-        let mut result = String::new();
-        for template in &self.templates {
-            result.push_str(&template.render()?);
-            result.push('\n');
+    #[test]
+    fn test_apply() -> Result<()> {
+        let mut template = TronTemplate::new("@[a]@ @[b]@")?;
+        let mut asked = Vec::new();
+        template.apply(|name| {
+            asked.push(name.to_string());
+            (name == "a").then(|| "first".to_string())
+        })?;
+
+        assert_eq!(asked, vec!["a", "b"]);
+        assert!(template.is_placeholder_set("a")?);
+        assert!(!template.is_placeholder_set("b")?);
+        assert_eq!(template.render_partial()?, "first @[b]@");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_to_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("src/generated/main.rs");
+        let mut main = TronRef::new(TronTemplate::new("fn @[name]@() {}")?);
+
+        assert!(main.render_to_file(&path).is_err());
+        assert!(!path.exists());
+
+        main.set("name", "main")?;
+        main.render_to_file(&path)?;
+        assert_eq!(fs::read_to_string(&path)?, "fn main() {}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_if_empty() -> Result<()> {
+        let mut template = TronTemplate::new("@[name]@: @[kind]@")?;
+        template.set("name", "main")?;
+
+        assert!(!template.set_if_empty("name", "default")?);
+        assert!(template.set_if_empty("kind", "fn")?);
+        assert!(!template.set_if_empty("kind", "struct")?);
+        assert_eq!(template.render()?, "main: fn");
+        assert!(template.set_if_empty("missing", "x").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_builder() -> Result<()> {
+        let mut template = TronTemplateBuilder::new()
+            .delimiters("{{", "}}")
+            .strict(true)
+            .build_from_str("fn {{ name }}() {}{{# generated }}")?;
+        template.set("name", "main")?;
+        assert_eq!(template.render()?, "fn main() {}");
+
+        let strict = TronTemplateBuilder::new().strict(true);
+        assert!(matches!(strict.build_from_str("fn @[name() {}"), Err(TronError::InvalidSyntax(_))));
+        assert!(TronTemplateBuilder::new().build_from_str("fn @[name() {}").is_ok());
+
+        let no_comments = TronTemplateBuilder::new().allow_comments(false);
+        match no_comments.build_from_str("ok\n  @[# note ]@") {
+            Err(TronError::ParseAt { line, column, .. }) => assert_eq!((line, column), (2, 3)),
+            other => panic!("expected a comment error, got {:?}", other),
         }
-        Ok(result)
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("main.tron");
+        fs::write(&path, "<% name %>")?;
+        let mut loaded = TronTemplateBuilder::new().delimiters("<%", "%>").build_from_file(&path)?;
+        loaded.set("name", "loaded")?;
+        assert_eq!(loaded.render()?, "loaded");
+        assert!(TronTemplateBuilder::new().delimiters("", "]").build_from_str("x").is_err());
+
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_try_render_all_collects_errors() -> Result<()> {
+        let mut assembler = TronAssembler::new();
+        assembler.add_template(TronRef::new(TronTemplate::new("struct First;")?));
+        assembler.add_template(TronRef::new(TronTemplate::new("struct @[missing]@;")?));
+        assembler.add_template(TronRef::new(TronTemplate::new("struct Third;")?));
+        assembler.add_template(TronRef::new(TronTemplate::new("@[env:TRON_TEST_SURELY_UNSET_VAR]@")?));
+
+        let (rendered, errors) = assembler.try_render_all();
+        assert_eq!(rendered, "struct First;\nstruct Third;\n");
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&errors[0], (1, TronError::MissingPlaceholder(name)) if name == "missing"));
+        assert!(matches!(&errors[1], (3, TronError::MissingEnvVar(_))));
+        assert!(assembler.render_all().is_err());
+
+        Ok(())
+    }
 
     #[test]
-    fn test_template_composition() -> Result<()> {
-        // Create a function template
-        let function = TronTemplate::new("fn @[name]@() {\n    @[body]@\n}")?;
-        let mut function_ref = TronRef::new(function);
-        
-        // Create a print template to insert into the function
-        let print = TronTemplate::new("println!(\"@[message]@\");")?;
-        let mut print_ref = TronRef::new(print);
-        print_ref.set("message", "Hello from Tron!")?;
-        
-        // Compose the templates
-        function_ref.set("name", "greet")?;
-        function_ref.set_ref("body", print_ref)?;
-        
-        let rendered = function_ref.render()?;
-        assert!(rendered.contains("fn greet()"));
-        assert!(rendered.contains("println!(\"Hello from Tron!\");"));
-        
+    fn test_placeholder_aliases() -> Result<()> {
+        let mut template = TronTemplate::new("[package]\nname = \"@[crate_name]@\"\n# @[pkg]@")?;
+        template.add_alias("crate_name", "pkg")?;
+        assert_eq!(template.required_placeholders(), vec!["crate_name", "pkg"]);
+
+        template.set("crate_name", "tron")?;
+        assert!(template.is_complete());
+        assert_eq!(template.render()?, "[package]\nname = \"tron\"\n# tron");
+
+        template.set("pkg", "override")?;
+        assert_eq!(template.render()?, "[package]\nname = \"tron\"\n# override");
+
+        let partial = TronTemplate::new("@[a]@ @[b]@ @[c]@").and_then(|mut t| {
+            t.add_alias("a", "b")?;
+            t.with_partial(&HashMap::from([("a".to_string(), "x".to_string())]))
+        })?;
+        assert_eq!(partial.render_partial()?, "x x @[c]@");
+
+        assert!(matches!(
+            template.add_alias("crate_name", "missing"),
+            Err(TronError::MissingPlaceholder(name)) if name == "missing"
+        ));
+
         Ok(())
     }
 
     #[test]
-    fn test_nested_composition() -> Result<()> {
-        let outer = TronTemplate::new("mod test {\n    @[function]@\n}")?;
-        let mut outer_ref = TronRef::new(outer);
-        
-        let inner = TronTemplate::new("fn helper() {\n    @[body]@\n}")?;
-        let mut inner_ref = TronRef::new(inner);
-        
-        let print = TronTemplate::new("println!(\"@[message]@\");")?;
-        let mut print_ref = TronRef::new(print);
-        print_ref.set("message", "Nested template")?;
-        
-        inner_ref.set_ref("body", print_ref)?;
-        outer_ref.set_ref("function", inner_ref)?;
-        
-        let rendered = outer_ref.render()?;
-        assert!(rendered.contains("mod test {"));
-        assert!(rendered.contains("fn helper()"));
-        assert!(rendered.contains("println!(\"Nested template\");"));
-        
+    fn test_scan_tags() -> Result<()> {
+        let inners = |content: &str, delimiters: &Delimiters| {
+            scan_tags(content, delimiters)
+                .iter()
+                .map(|tag| tag.inner.to_string())
+                .collect::<Vec<_>>()
+        };
+        let default = Delimiters::default();
+
+        assert_eq!(inners("@[a]@ and @[ b = 1 ]@", &default), vec!["a", " b = 1 "]);
+        assert_eq!(inners("@[a @[b]@ ]@", &default), vec!["b"]);
+        assert_eq!(inners("@[]@ @[multi\nline]@", &default), vec!["multi\nline"]);
+        assert_eq!(inners("@@[a]@ @@@[b]@ @[c", &default), vec!["b"]);
+
+        let braces = Delimiters::new("{{", "}}")?;
+        assert_eq!(inners("{{a}} {{{b}} {{{{c}}", &braces), vec!["a", "c"]);
+
+        let tags = scan_tags("x @[a]@ y", &default);
+        assert_eq!(tags.iter().map(|tag| tag.span.clone()).collect::<Vec<_>>(), vec![2..7]);
+
         Ok(())
     }
-}
\ No newline at end of file
+}